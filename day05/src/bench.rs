@@ -0,0 +1,171 @@
+use std::fmt::Write;
+use std::time::Instant;
+
+use crate::crane::{Crane, CrateMover9000, CrateMover9001};
+use crate::get_answer;
+
+/// Small xorshift generator, so the generated inputs are the same on every run.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        //A state of 0 only ever gives 0, so that seed gets another fixed state
+        XorShift(if seed == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            seed
+        })
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GenerateError {
+    //Moves need a pile to take crates from and another one to put them on
+    TooFewPiles(usize),
+    //Moves were asked for without any crate to move, or while moving none at a time
+    NothingToMove,
+}
+
+/// Generates an input with `n_piles` piles of `height` crates and `n_moves` valid moves of at most
+/// `max_crates_to_move` crates each.
+pub fn generate_input(
+    n_piles: usize,
+    height: usize,
+    n_moves: usize,
+    max_crates_to_move: usize,
+    seed: u64,
+) -> Result<String, GenerateError> {
+    if n_piles < 2 {
+        return Err(GenerateError::TooFewPiles(n_piles));
+    }
+    if n_moves > 0 && (height == 0 || max_crates_to_move == 0) {
+        return Err(GenerateError::NothingToMove);
+    }
+    let mut rng = XorShift::new(seed);
+    let mut input = String::new();
+
+    for _ in 0..height {
+        let layer: Vec<String> = (0..n_piles)
+            .map(|_| format!("[{}]", (b'A' + rng.below(26) as u8) as char))
+            .collect();
+        input += &layer.join(" ");
+        input.push('\n');
+    }
+    let footer: Vec<String> = (1..=n_piles)
+        .map(|number| format!("{:^3}", number))
+        .collect();
+    input += &footer.join(" ");
+    input += "\n\n";
+
+    let mut heights = vec![height; n_piles];
+    for _ in 0..n_moves {
+        //There are always crates somewhere, since moves only carry them between piles
+        let non_empty: Vec<usize> = (0..n_piles).filter(|&pile| heights[pile] > 0).collect();
+        let from_pile = non_empty[rng.below(non_empty.len())];
+        let to_pile = (from_pile + 1 + rng.below(n_piles - 1)) % n_piles;
+        let crates_to_move = 1 + rng.below(heights[from_pile].min(max_crates_to_move));
+        heights[from_pile] -= crates_to_move;
+        heights[to_pile] += crates_to_move;
+        writeln!(
+            input,
+            "move {} from {} to {}",
+            crates_to_move,
+            from_pile + 1,
+            to_pile + 1
+        )
+        .unwrap();
+    }
+
+    Ok(input)
+}
+
+/// Times both CrateMovers on generated inputs of growing size.
+pub fn run() {
+    const N_PILES: usize = 9;
+    const HEIGHT: usize = 100_000;
+
+    for (n_moves, max_crates_to_move) in [(100_000, 1_000), (1_000_000, 100), (1_000_000, 1_000)] {
+        let start = Instant::now();
+        let input = generate_input(N_PILES, HEIGHT, n_moves, max_crates_to_move, 42)
+            .expect("Expecting valid benchmark parameters");
+        println!(
+            "{} piles of {} crates, {} moves of up to {} crates ({:.1} MB, generated in {:.2?})",
+            N_PILES,
+            HEIGHT,
+            n_moves,
+            max_crates_to_move,
+            input.len() as f64 / 1e6,
+            start.elapsed()
+        );
+
+        let cranes: [(&str, &dyn Crane); 2] = [
+            ("CrateMover9000", &CrateMover9000),
+            ("CrateMover9001", &CrateMover9001),
+        ];
+        for (name, crane) in cranes {
+            let start = Instant::now();
+            let top_crates = get_answer(input.as_bytes(), crane, false).unwrap();
+            println!(
+                "    {}: {} in {:.2?}",
+                name,
+                &top_crates[..top_crates.len().min(9)],
+                start.elapsed()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_init_piles, ActionReader};
+
+    #[test]
+    fn test_generate_input() {
+        let input = generate_input(12, 20, 500, 30, 7).unwrap();
+        let mut reader = input.as_bytes();
+        let piles = read_init_piles(&mut reader).unwrap();
+        assert_eq!(piles.piles.len(), 12);
+        assert!(piles.piles.iter().all(|pile| pile.len() == 20));
+        assert_eq!(ActionReader::new(reader).count(), 500);
+
+        assert!(get_answer(input.as_bytes(), &CrateMover9000, false).is_ok());
+        assert!(get_answer(input.as_bytes(), &CrateMover9001, false).is_ok());
+
+        assert_eq!(
+            generate_input(1, 20, 500, 30, 7),
+            Err(GenerateError::TooFewPiles(1))
+        );
+        assert_eq!(
+            generate_input(0, 20, 0, 30, 7),
+            Err(GenerateError::TooFewPiles(0))
+        );
+        assert_eq!(
+            generate_input(3, 0, 10, 30, 7),
+            Err(GenerateError::NothingToMove)
+        );
+        assert_eq!(
+            generate_input(3, 20, 10, 0, 7),
+            Err(GenerateError::NothingToMove)
+        );
+        assert!(generate_input(2, 0, 0, 0, 7).is_ok());
+
+        //More moves than crates in a pile, from a seed of 0
+        let input = generate_input(3, 2, 50, 1, 0).unwrap();
+        let mut reader = input.as_bytes();
+        read_init_piles(&mut reader).unwrap();
+        assert_eq!(ActionReader::new(reader).count(), 50);
+        assert!(get_answer(input.as_bytes(), &CrateMover9000, false).is_ok());
+    }
+}
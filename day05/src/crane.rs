@@ -0,0 +1,237 @@
+use std::str::FromStr;
+
+use crate::executor::{validate, MoveError};
+use crate::{Action, MyCrate, Pile, Piles};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum End {
+    Top,
+    Bottom,
+}
+
+/// A crane model. Each model lifts crates off a pile and drops them on another in its own way.
+/// An action that cannot be done leaves the piles untouched.
+pub trait Crane {
+    fn execute(&self, piles: &mut Piles, action: &Action) -> Result<(), MoveError>;
+
+    /// The end of the pile crates are lifted from. Whatever the model, they land on top of the
+    /// other pile.
+    fn lifts_from(&self) -> End {
+        End::Top
+    }
+}
+
+/// The pile the crates of an action are lifted from, once both piles exist and it has enough
+/// crates.
+fn source_pile<'a>(piles: &'a mut Piles, action: &Action) -> Result<&'a mut Pile, MoveError> {
+    validate(piles, action)?;
+    Ok(&mut piles.piles[action.from_pile])
+}
+
+/// Takes the crates of an action at the top of its pile, in the order they were stacked.
+fn lift_from_top(piles: &mut Piles, action: &Action) -> Result<Vec<MyCrate>, MoveError> {
+    let pile = source_pile(piles, action)?;
+    let remaining = pile.len() - action.crates_to_move;
+    Ok(pile.split_off(remaining))
+}
+
+/// Moves crates one at a time, so a move reverses their order.
+pub struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn execute(&self, piles: &mut Piles, action: &Action) -> Result<(), MoveError> {
+        let lifted = lift_from_top(piles, action)?;
+        piles.piles[action.to_pile].extend(lifted.into_iter().rev());
+        Ok(())
+    }
+}
+
+/// Moves all the crates of an action at once, so their order is kept.
+pub struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn execute(&self, piles: &mut Piles, action: &Action) -> Result<(), MoveError> {
+        let mut lifted = lift_from_top(piles, action)?;
+        piles.piles[action.to_pile].append(&mut lifted);
+        Ok(())
+    }
+}
+
+/// Moves at most `capacity` crates at once. Larger moves are split in batches, each batch keeping
+/// its order, the last one being possibly smaller.
+pub struct CappedCrane {
+    pub capacity: usize,
+}
+
+impl Crane for CappedCrane {
+    fn execute(&self, piles: &mut Piles, action: &Action) -> Result<(), MoveError> {
+        //Checked as a whole, so a move that cannot be done is not left halfway
+        validate(piles, action)?;
+        let mut remaining = action.crates_to_move;
+        while remaining > 0 {
+            let batch = remaining.min(self.capacity);
+            CrateMover9001.execute(
+                piles,
+                &Action {
+                    crates_to_move: batch,
+                    ..*action
+                },
+            )?;
+            remaining -= batch;
+        }
+        Ok(())
+    }
+}
+
+/// Can only grab the bottom of a pile: lifts the lowest crates as one block and sets them on top
+/// of the target pile, keeping their order.
+pub struct BottomLiftCrane;
+
+impl Crane for BottomLiftCrane {
+    fn execute(&self, piles: &mut Piles, action: &Action) -> Result<(), MoveError> {
+        let pile = source_pile(piles, action)?;
+        let lifted: Vec<MyCrate> = pile.drain(..action.crates_to_move).collect();
+        piles.piles[action.to_pile].extend(lifted);
+        Ok(())
+    }
+
+    fn lifts_from(&self) -> End {
+        End::Bottom
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CraneModel {
+    CrateMover9000,
+    CrateMover9001,
+    Capped(usize),
+    BottomLift,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseCraneModelError(pub String);
+
+impl FromStr for CraneModel {
+    type Err = ParseCraneModelError;
+
+    /// Accepts `9000`, `9001`, `capped:<capacity>` and `bottom`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("capped", capacity)) => match capacity.parse() {
+                Ok(capacity) if capacity > 0 => Ok(CraneModel::Capped(capacity)),
+                _ => Err(ParseCraneModelError(format!(
+                    "Expected a positive crane capacity but found '{}'",
+                    capacity
+                ))),
+            },
+            _ => match s {
+                "9000" => Ok(CraneModel::CrateMover9000),
+                "9001" => Ok(CraneModel::CrateMover9001),
+                "bottom" => Ok(CraneModel::BottomLift),
+                _ => Err(ParseCraneModelError(format!("Unknown crane model '{}'", s))),
+            },
+        }
+    }
+}
+
+impl CraneModel {
+    pub fn build(self) -> Box<dyn Crane> {
+        match self {
+            CraneModel::CrateMover9000 => Box::new(CrateMover9000),
+            CraneModel::CrateMover9001 => Box::new(CrateMover9001),
+            CraneModel::Capped(capacity) => Box::new(CappedCrane { capacity }),
+            CraneModel::BottomLift => Box::new(BottomLiftCrane),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piles_from(piles: &[&[&str]]) -> Piles {
+        Piles {
+            piles: piles
+                .iter()
+                .map(|pile| pile.iter().map(|c| c.to_string()).collect())
+                .collect(),
+        }
+    }
+
+    fn run(crane: &dyn Crane, crates_to_move: usize) -> Vec<Vec<MyCrate>> {
+        let mut piles = piles_from(&[&["A", "B", "C", "D", "E"], &[]]);
+        crane
+            .execute(
+                &mut piles,
+                &Action {
+                    crates_to_move,
+                    from_pile: 0,
+                    to_pile: 1,
+                },
+            )
+            .unwrap();
+        piles.piles
+    }
+
+    #[test]
+    fn test_crane_models() {
+        assert_eq!(run(&CrateMover9000, 3)[1], vec!["E", "D", "C"]);
+        assert_eq!(run(&CrateMover9001, 3)[1], vec!["C", "D", "E"]);
+        assert_eq!(
+            run(&CappedCrane { capacity: 2 }, 5)[1],
+            vec!["D", "E", "B", "C", "A"]
+        );
+        assert_eq!(
+            run(&BottomLiftCrane, 2),
+            vec![vec!["C", "D", "E"], vec!["A", "B"]]
+        );
+    }
+
+    #[test]
+    fn test_impossible_moves() {
+        let cranes: [&dyn Crane; 4] = [
+            &CrateMover9000,
+            &CrateMover9001,
+            &CappedCrane { capacity: 2 },
+            &BottomLiftCrane,
+        ];
+        for crane in cranes {
+            let mut piles = piles_from(&[&["A", "B", "C"], &[]]);
+            let too_many = Action {
+                crates_to_move: 4,
+                from_pile: 0,
+                to_pile: 1,
+            };
+            assert_eq!(
+                crane.execute(&mut piles, &too_many),
+                Err(MoveError::NotEnoughCrates {
+                    pile_idx: 0,
+                    available: 3,
+                    requested: 4
+                })
+            );
+            let unknown_pile = Action {
+                crates_to_move: 1,
+                from_pile: 0,
+                to_pile: 2,
+            };
+            assert_eq!(
+                crane.execute(&mut piles, &unknown_pile),
+                Err(MoveError::UnknownPile {
+                    pile_idx: 2,
+                    n_piles: 2
+                })
+            );
+            assert_eq!(piles, piles_from(&[&["A", "B", "C"], &[]]));
+        }
+    }
+
+    #[test]
+    fn test_parse_crane_model() {
+        assert_eq!("9000".parse(), Ok(CraneModel::CrateMover9000));
+        assert_eq!("capped:3".parse(), Ok(CraneModel::Capped(3)));
+        assert_eq!("bottom".parse(), Ok(CraneModel::BottomLift));
+        assert!("capped:0".parse::<CraneModel>().is_err());
+        assert!("9002".parse::<CraneModel>().is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use crate::crane::{Crane, End};
+use crate::{Action, MyCrate, Piles};
+
+#[derive(Debug, PartialEq)]
+pub enum MoveError {
+    UnknownPile {
+        pile_idx: usize,
+        n_piles: usize,
+    },
+    NotEnoughCrates {
+        pile_idx: usize,
+        available: usize,
+        requested: usize,
+    },
+    UnknownStep {
+        step: usize,
+        n_steps: usize,
+    },
+}
+
+/// Checks that the piles of an action exist and that there are enough crates to move.
+pub fn validate(piles: &Piles, action: &Action) -> Result<(), MoveError> {
+    let n_piles = piles.piles.len();
+    for pile_idx in [action.from_pile, action.to_pile] {
+        if pile_idx >= n_piles {
+            return Err(MoveError::UnknownPile { pile_idx, n_piles });
+        }
+    }
+
+    let available = piles.piles[action.from_pile].len();
+    if available < action.crates_to_move {
+        return Err(MoveError::NotEnoughCrates {
+            pile_idx: action.from_pile,
+            available,
+            requested: action.crates_to_move,
+        });
+    }
+    Ok(())
+}
+
+/// An action of the history, with the crates it lifted in the order they were in their pile while
+/// it is applied. Only those are kept, so a move costs the same whatever the height of the piles.
+struct HistoryEntry {
+    action: Action,
+    lifted: Vec<MyCrate>,
+}
+
+/// Applies actions through a crane after checking they can be carried out, and keeps a history of
+/// them so the piles can be moved back and forth between steps.
+pub struct Executor<'a> {
+    crane: &'a dyn Crane,
+    piles: Piles,
+    history: Vec<HistoryEntry>,
+    //Number of entries of the history currently applied, the others can be redone
+    step: usize,
+}
+
+impl<'a> Executor<'a> {
+    pub fn new(piles: Piles, crane: &'a dyn Crane) -> Self {
+        Executor {
+            crane,
+            piles,
+            history: Vec::new(),
+            step: 0,
+        }
+    }
+
+    pub fn piles(&self) -> &Piles {
+        &self.piles
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &Action> {
+        self.history.iter().map(|entry| &entry.action)
+    }
+
+    /// Applies a new action. The actions that were undone are dropped from the history, and an
+    /// invalid action leaves both the piles and the history untouched.
+    pub fn apply(&mut self, action: Action) -> Result<(), MoveError> {
+        validate(&self.piles, &action)?;
+        self.history.truncate(self.step);
+        self.history.push(HistoryEntry {
+            action,
+            lifted: Vec::new(),
+        });
+        self.redo();
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> bool {
+        if self.step == 0 {
+            return false;
+        }
+        self.step -= 1;
+
+        //Every crane leaves the lifted crates on top of the target pile, so they are taken back from
+        //there and put back where they were lifted
+        let entry = &mut self.history[self.step];
+        let action = entry.action;
+        let piles = &mut self.piles.piles;
+        let to_pile = &mut piles[action.to_pile];
+        to_pile.truncate(to_pile.len() - action.crates_to_move);
+        let lifted = std::mem::take(&mut entry.lifted);
+        match self.crane.lifts_from() {
+            End::Top => piles[action.from_pile].extend(lifted),
+            End::Bottom => {
+                piles[action.from_pile].splice(0..0, lifted);
+            }
+        }
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(entry) = self.history.get_mut(self.step) else {
+            return false;
+        };
+        self.step += 1;
+
+        let from_pile = &self.piles.piles[entry.action.from_pile];
+        let n = entry.action.crates_to_move;
+        entry.lifted = match self.crane.lifts_from() {
+            End::Top => from_pile[from_pile.len() - n..].to_vec(),
+            End::Bottom => from_pile[..n].to_vec(),
+        };
+        self.crane
+            .execute(&mut self.piles, &entry.action)
+            .expect("Expecting the actions of the history to have been validated");
+        true
+    }
+
+    /// Undoes or redoes actions until exactly `step` actions of the history are applied.
+    pub fn replay_to(&mut self, step: usize) -> Result<(), MoveError> {
+        if step > self.history.len() {
+            return Err(MoveError::UnknownStep {
+                step,
+                n_steps: self.history.len(),
+            });
+        }
+        while self.step > step {
+            self.undo();
+        }
+        while self.step < step {
+            self.redo();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crane::CrateMover9001;
+    use crate::{get_top_crates, parse_init_piles};
+
+    fn action(crates_to_move: usize, from_pile: usize, to_pile: usize) -> Action {
+        Action {
+            crates_to_move,
+            from_pile,
+            to_pile,
+        }
+    }
+
+    #[test]
+    fn test_invalid_moves() {
+        let piles = parse_init_piles("[A]\n[B] [C]\n 1   2").unwrap();
+        let mut executor = Executor::new(piles, &CrateMover9001);
+
+        assert_eq!(
+            executor.apply(action(1, 0, 2)),
+            Err(MoveError::UnknownPile {
+                pile_idx: 2,
+                n_piles: 2
+            })
+        );
+        assert_eq!(
+            executor.apply(action(2, 1, 0)),
+            Err(MoveError::NotEnoughCrates {
+                pile_idx: 1,
+                available: 1,
+                requested: 2
+            })
+        );
+        assert_eq!(executor.step(), 0);
+        assert_eq!(executor.apply(action(2, 0, 1)), Ok(()));
+        assert_eq!(get_top_crates(executor.piles()), "A");
+    }
+
+    #[test]
+    fn test_undo_every_crane() {
+        let drawing = "[A]\n[B] [C]\n[D] [E] [F]\n 1   2   3";
+        let actions = [
+            action(2, 0, 1),
+            action(3, 1, 2),
+            action(1, 2, 2),
+            action(4, 2, 0),
+            action(2, 0, 0),
+        ];
+        for model in ["9000", "9001", "capped:2", "bottom"] {
+            let crane = model.parse::<crate::crane::CraneModel>().unwrap().build();
+            let start = parse_init_piles(drawing).unwrap();
+            let mut executor = Executor::new(start.clone(), crane.as_ref());
+            let mut states = vec![start];
+            for action in actions {
+                executor.apply(action).unwrap();
+                states.push(executor.piles().clone());
+            }
+            for step in (0..states.len()).rev().chain(0..states.len()) {
+                executor.replay_to(step).unwrap();
+                assert_eq!(
+                    executor.piles(),
+                    &states[step],
+                    "{} at step {}",
+                    model,
+                    step
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_undo_redo_replay() {
+        let piles = parse_init_piles("[A]\n[B] [C]\n 1   2").unwrap();
+        let mut executor = Executor::new(piles, &CrateMover9001);
+        executor.apply(action(1, 0, 1)).unwrap();
+        executor.apply(action(2, 1, 0)).unwrap();
+        assert_eq!(
+            executor.piles().to_string(),
+            "[A]    \n[C]    \n[B]    \n 1   2 "
+        );
+
+        assert!(executor.undo());
+        assert_eq!(executor.piles().to_string(), "    [A]\n[B] [C]\n 1   2 ");
+        assert!(executor.undo());
+        assert!(!executor.undo());
+        assert_eq!(executor.piles().to_string(), "[A]    \n[B] [C]\n 1   2 ");
+
+        executor.replay_to(2).unwrap();
+        assert_eq!(get_top_crates(executor.piles()), "A");
+        assert!(executor.replay_to(3).is_err());
+
+        executor.replay_to(1).unwrap();
+        executor.apply(action(1, 1, 0)).unwrap();
+        assert!(!executor.redo());
+        assert_eq!(executor.history().count(), 2);
+    }
+}
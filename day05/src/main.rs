@@ -1,18 +1,27 @@
-use std::cell::RefCell;
-use std::fs::read_to_string;
+mod bench;
+mod crane;
+mod executor;
+mod planner;
+
+use crane::{Crane, CraneModel, CrateMover9000, CrateMover9001};
+use executor::{Executor, MoveError};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
 use std::path::Path;
 use std::{format, println, vec, write};
 
+#[derive(Clone, Copy, PartialEq)]
 struct Action {
     crates_to_move: usize,
     from_pile: usize,
     to_pile: usize,
 }
 
-type MyCrate = char;
+type MyCrate = String;
 type Pile = Vec<MyCrate>;
+#[derive(Clone, PartialEq)]
 struct Piles {
-    piles: Vec<RefCell<Pile>>,
+    piles: Vec<Pile>,
 }
 
 impl std::fmt::Debug for Action {
@@ -25,123 +34,306 @@ impl std::fmt::Debug for Action {
     }
 }
 
+/// Writes the action as a line of the input, with piles numbered from 1.
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "move {} from {} to {}",
+            self.crates_to_move,
+            self.from_pile + 1,
+            self.to_pile + 1
+        )
+    }
+}
+
 impl std::fmt::Debug for Piles {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut output = String::new();
-        for (i, pile) in self.piles.iter().map(|pile| pile.borrow()).enumerate() {
+        for (i, pile) in self.piles.iter().enumerate() {
             output += &format!("(Pile: {}): {:?}\n", i, pile);
         }
         write!(f, "{}", output)
     }
 }
 
-fn parse_config_line(line: &str, num_piles: usize) -> Vec<MyCrate> {
-    let mut output = vec![' '; num_piles];
-    let chars: Vec<_> = line.chars().collect();
+/// Renders the piles in the drawing format of the input, which `parse_init_piles` reads back.
+/// Every column is as wide as the widest crate label, and columns are seperated by a space. Crates
+/// and pile numbers are both centred in their column, so every crate sits over its number.
+impl std::fmt::Display for Piles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let piles = &self.piles;
+        let label_width = piles
+            .iter()
+            .flat_map(|pile| pile.iter().map(|my_crate| my_crate.chars().count()))
+            .max()
+            .unwrap_or(1);
+        let column_widths: Vec<usize> = (1..=piles.len())
+            .map(|number| (label_width + 2).max(number.to_string().len()))
+            .collect();
+        let max_height = piles.iter().map(|pile| pile.len()).max().unwrap_or(0);
+
+        for height in (0..max_height).rev() {
+            let cells: Vec<String> = piles
+                .iter()
+                .zip(&column_widths)
+                .map(|(pile, &width)| match pile.get(height) {
+                    Some(my_crate) => format!("{:^width$}", format!("[{}]", my_crate)),
+                    None => " ".repeat(width),
+                })
+                .collect();
+            writeln!(f, "{}", cells.join(" "))?;
+        }
 
-    for i in 0..num_piles {
-        output[i] = chars[i * 4 + 1];
+        let footer: Vec<String> = column_widths
+            .iter()
+            .enumerate()
+            .map(|(i, &width)| {
+                let number = (i + 1).to_string();
+                let padding = (width - number.len()) / 2;
+                format!("{:<width$}", " ".repeat(padding) + &number)
+            })
+            .collect();
+        write!(f, "{}", footer.join(" "))
     }
+}
 
-    return output;
+#[derive(Debug, PartialEq)]
+enum ParsingError {
+    Footer(String),
+    CrateLine(usize, String),
+    Action(usize, String),
+    Io(String),
 }
 
-fn parse_init_piles(init_piles: &str) -> Piles {
-    let mut lines = init_piles.lines().rev();
-    let first_line = lines
-        .next()
-        .expect("Initiziale configuration expected at beginning of the file")
-        .to_string();
+#[derive(Debug, PartialEq)]
+enum SimulationError {
+    Parsing(ParsingError),
+    //The move number, starting at 1, and why it could not be done
+    Move(usize, MoveError),
+}
 
-    let (num_piles, check): (usize, usize) =
-        ((first_line.len() + 1) / 4, (first_line.len() + 1) % 4);
+impl From<ParsingError> for SimulationError {
+    fn from(error: ParsingError) -> Self {
+        SimulationError::Parsing(error)
+    }
+}
+
+/// A column of the drawing, located by the span of its number in the footer line.
+struct Column {
+    start: usize,
+    end: usize,
+}
 
-    assert!(
-        check == 0,
-        "The initial configuration could not be read properly"
-    );
+fn parse_footer(line: &str) -> Result<Vec<Column>, ParsingError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut columns = Vec::new();
 
-    let mut config: Vec<Vec<MyCrate>> = Vec::new();
-    for line in lines {
-        config.push(parse_config_line(line, num_piles));
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() {
+            i += 1;
+        }
+        let label: String = chars[start..i].iter().collect();
+        let number: usize = label.parse().map_err(|_| {
+            ParsingError::Footer(format!("Expected a pile number but found '{}'", label))
+        })?;
+        if number != columns.len() + 1 {
+            return Err(ParsingError::Footer(format!(
+                "Expected pile number {} but found {}",
+                columns.len() + 1,
+                number
+            )));
+        }
+        columns.push(Column { start, end: i - 1 });
     }
 
-    //init_config is now a vector where each element is a horizontal line!()
-    //Change it so that each element is a vertical pile and clean up by removing air spaces
-    //Basiccaly a transpose, but we also remove the air spaces
-    let mut piles = Vec::new();
-    let max_height = config.len();
+    if columns.is_empty() {
+        return Err(ParsingError::Footer(
+            "Expected the last line of the drawing to number the piles".to_string(),
+        ));
+    }
+    Ok(columns)
+}
 
-    for i in 0..num_piles {
-        let mut pile: Pile = Vec::new();
-        for j in 0..max_height {
-            let my_crate = config[j][i];
-            if my_crate == ' ' {
-                break;
+/// Parses one horizontal layer of the drawing. Each bracketed crate is assigned to the column
+/// whose footer number it sits over, so trimmed lines and labels of any width are accepted.
+fn parse_config_line(
+    line: &str,
+    line_number: usize,
+    columns: &[Column],
+) -> Result<Vec<Option<MyCrate>>, ParsingError> {
+    let error = |message: String| ParsingError::CrateLine(line_number, message);
+    let chars: Vec<char> = line.chars().collect();
+    let mut output = vec![None; columns.len()];
+
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            c if c.is_whitespace() => i += 1,
+            '[' => {
+                let start = i;
+                let end = (start + 1..chars.len())
+                    .find(|&j| chars[j] == ']')
+                    .ok_or_else(|| error(format!("Unclosed crate at position {}", start)))?;
+                let label: String = chars[start + 1..end].iter().collect();
+                if label.trim().is_empty() {
+                    return Err(error(format!("Empty crate at position {}", start)));
+                }
+
+                let column_idx = columns
+                    .iter()
+                    .position(|column| start <= column.end && column.start <= end)
+                    .ok_or_else(|| {
+                        error(format!(
+                            "Crate [{}] at position {} is not above any pile number",
+                            label, start
+                        ))
+                    })?;
+                if output[column_idx].is_some() {
+                    return Err(error(format!(
+                        "Two crates above pile number {}",
+                        column_idx + 1
+                    )));
+                }
+                output[column_idx] = Some(label);
+                i = end + 1;
+            }
+            c => {
+                return Err(error(format!(
+                    "Unexpected character '{}' at position {}",
+                    c, i
+                )))
             }
-            pile.push(my_crate);
         }
+    }
 
-        piles.push(RefCell::new(pile));
+    Ok(output)
+}
+
+fn parse_init_piles(init_piles: &str) -> Result<Piles, ParsingError> {
+    let lines: Vec<_> = init_piles
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .collect();
+    let mut lines = lines.into_iter().rev();
+    let (_, footer) = lines.next().ok_or_else(|| {
+        ParsingError::Footer("Initial configuration expected at beginning of the file".to_string())
+    })?;
+    let columns = parse_footer(footer)?;
+
+    let mut piles: Vec<Pile> = vec![Vec::new(); columns.len()];
+    for (i, line) in lines {
+        let layer = parse_config_line(line, i + 1, &columns)?;
+
+        //Layers are read bottom up, so a crate can only be placed on a pile that has been
+        //filled up to the current height
+        let height = piles.iter().map(|pile| pile.len()).max().unwrap_or(0);
+        for (pile_idx, my_crate) in layer.into_iter().enumerate() {
+            if let Some(my_crate) = my_crate {
+                if piles[pile_idx].len() != height {
+                    return Err(ParsingError::CrateLine(
+                        i + 1,
+                        format!(
+                            "Crate [{}] is floating above pile {}",
+                            my_crate,
+                            pile_idx + 1
+                        ),
+                    ));
+                }
+                piles[pile_idx].push(my_crate);
+            }
+        }
     }
 
-    Piles { piles }
+    Ok(Piles { piles })
 }
 
-fn parse_action(line: &str) -> Action {
-    let splits: Vec<_> = line.split(" ").collect();
-    let mut numbers: Vec<usize> = Vec::new();
+fn parse_action(line: &str, line_number: usize) -> Result<Action, ParsingError> {
+    let error = |message: &str| ParsingError::Action(line_number, message.to_string());
+    let mut words = line.split_whitespace();
+    let mut numbers = [0_usize; 3];
 
-    for i in [1, 3, 5] {
-        numbers.push(splits[i].parse().expect("Expecting valid move line."));
+    for (i, keyword) in ["move", "from", "to"].into_iter().enumerate() {
+        if words.next() != Some(keyword) {
+            return Err(error("Expected a move line like 'move 1 from 2 to 3'"));
+        }
+        numbers[i] = words
+            .next()
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(|| error("Expected the counts and piles to be positive numbers"))?;
+    }
+    if words.next().is_some() {
+        return Err(error("Expected a move line like 'move 1 from 2 to 3'"));
+    }
+    if numbers[1] == 0 || numbers[2] == 0 {
+        return Err(error("Piles are numbered starting at 1"));
     }
 
-    Action {
+    Ok(Action {
         crates_to_move: numbers[0],
         from_pile: numbers[1] - 1,
         to_pile: numbers[2] - 1,
-    }
+    })
 }
 
-fn parse_input(input: &String) -> (&str, &str) {
-    let wrong_frmt_msg = "Expecting input format where the inital configuration and moves are seperated by an empty line";
-    let (init_piles, moves) = input.split_once("\n\n").expect(wrong_frmt_msg);
-    return (init_piles, moves);
+/// Reads the drawing at the start of the input, up to the empty line before the moves.
+fn read_init_piles<R: BufRead>(reader: &mut R) -> Result<Piles, ParsingError> {
+    let mut drawing = String::new();
+    loop {
+        let len_before = drawing.len();
+        let n_read = reader
+            .read_line(&mut drawing)
+            .map_err(|error| ParsingError::Io(error.to_string()))?;
+        if n_read == 0
+            || drawing[len_before..]
+                .trim_end_matches(['\r', '\n'])
+                .is_empty()
+        {
+            break;
+        }
+    }
+    parse_init_piles(&drawing)
 }
 
-fn execute_action_a(piles: &mut Piles, action: &Action) {
-    let crate_it = (0..action.crates_to_move).map(|_| {
-        piles.piles[action.from_pile]
-            .borrow_mut()
-            .pop()
-            .expect(&format!(
-                "Was expeting a crate in pile {}",
-                action.from_pile
-            ))
-    });
+/// Parses the moves one line at a time, so that they never all sit in memory.
+struct ActionReader<R: BufRead> {
+    reader: R,
+    line: String,
+    line_number: usize,
+}
 
-    for my_crate in crate_it {
-        piles.piles[action.to_pile].borrow_mut().push(my_crate);
+impl<R: BufRead> ActionReader<R> {
+    fn new(reader: R) -> Self {
+        ActionReader {
+            reader,
+            line: String::new(),
+            line_number: 0,
+        }
     }
 }
 
-fn execute_action_b(piles: &mut Piles, action: &Action) {
-    let crate_it = (0..action.crates_to_move)
-        .map(|_| {
-            piles.piles[action.from_pile]
-                .borrow_mut()
-                .pop()
-                .expect(&format!(
-                    "Was expeting a crate in pile {}",
-                    action.from_pile
-                ))
-        })
-        .collect::<Vec<_>>() //Have to collect to map before the reverse or else does not work
-        .into_iter()
-        .rev();
+impl<R: BufRead> Iterator for ActionReader<R> {
+    type Item = Result<Action, ParsingError>;
 
-    for my_crate in crate_it {
-        piles.piles[action.to_pile].borrow_mut().push(my_crate);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => self.line_number += 1,
+                Err(error) => return Some(Err(ParsingError::Io(error.to_string()))),
+            }
+            if !self.line.trim().is_empty() {
+                return Some(parse_action(&self.line, self.line_number));
+            }
+        }
     }
 }
 
@@ -149,38 +341,287 @@ fn get_top_crates(piles: &Piles) -> String {
     piles
         .piles
         .iter()
-        .map(|pile| *pile.borrow().last().unwrap())
+        .filter_map(|pile| pile.last().cloned())
         .collect()
 }
 
-fn get_answer_a(input: &String) -> String {
-    let (init_piles, moves) = parse_input(&input);
-    let mut piles = parse_init_piles(init_piles);
-    let actions = moves.lines().map(|line| parse_action(line));
+/// Applies the actions one after the other, stopping at the first one that cannot be done.
+fn run_actions(
+    executor: &mut Executor,
+    actions: &[Action],
+    trace: bool,
+) -> Result<(), SimulationError> {
+    if trace {
+        println!("{}\n", executor.piles());
+    }
+    for (i, action) in actions.iter().enumerate() {
+        executor
+            .apply(*action)
+            .map_err(|error| SimulationError::Move(i + 1, error))?;
+        if trace {
+            println!("{:?}\n{}\n", action, executor.piles());
+        }
+    }
+    Ok(())
+}
+
+fn get_answer<R: BufRead>(
+    mut reader: R,
+    crane: &dyn Crane,
+    trace: bool,
+) -> Result<String, SimulationError> {
+    let mut piles = read_init_piles(&mut reader)?;
 
-    for action in actions {
-        execute_action_a(&mut piles, &action);
+    if trace {
+        println!("{}\n", piles);
+    }
+    for (i, action) in ActionReader::new(reader).enumerate() {
+        let action = action?;
+        crane
+            .execute(&mut piles, &action)
+            .map_err(|error| SimulationError::Move(i + 1, error))?;
+        if trace {
+            println!("{:?}\n{}\n", action, piles);
+        }
     }
 
-    get_top_crates(&piles)
+    Ok(get_top_crates(&piles))
 }
 
-fn get_answer_b(input: &String) -> String {
-    let (init_piles, moves) = parse_input(&input);
-    let mut piles = parse_init_piles(init_piles);
-    let actions = moves.lines().map(|line| parse_action(line));
+fn open_input(path: &str) -> BufReader<File> {
+    BufReader::new(File::open(Path::new(path)).expect("Expecting valid input file"))
+}
+
+fn main() {
+    //Usage: day5 [--input <file>] [--crane <9000|9001|capped:N|bottom>] [--trace] [--step N]
+    //             [--plan <start drawing file> <target drawing file>] [--bench]
+    let mut input_path = "./input/input.txt".to_string();
+    let mut model: Option<CraneModel> = None;
+    let mut trace = false;
+    let mut step: Option<usize> = None;
+    let mut plan_files: Option<(String, String)> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input_path = args.next().expect("Expecting a file after --input"),
+            "--bench" => {
+                bench::run();
+                return;
+            }
+            "--plan" => {
+                let mut next_file = || {
+                    args.next()
+                        .expect("Expecting two drawing files after --plan")
+                };
+                plan_files = Some((next_file(), next_file()));
+            }
+            "--crane" => {
+                model = Some(
+                    args.next()
+                        .expect("Expecting a crane model after --crane")
+                        .parse()
+                        .unwrap(),
+                )
+            }
+            "--trace" => trace = true,
+            "--step" => {
+                step = Some(
+                    args.next()
+                        .and_then(|step| step.parse().ok())
+                        .expect("Expecting a move number after --step"),
+                )
+            }
+            arg => panic!("Unknown argument: {}", arg),
+        }
+    }
+
+    if let Some((start_file, target_file)) = plan_files {
+        //The drawings may be followed by moves, which are ignored
+        let read_drawing = |file: &str| read_init_piles(&mut open_input(file)).unwrap();
+        let model = model.unwrap_or(CraneModel::CrateMover9000);
+        let start = read_drawing(&start_file);
+        let target = read_drawing(&target_file);
+        const MAX_STATES: usize = 1_000_000;
+        match planner::plan(&start, &target, model.build().as_ref(), MAX_STATES) {
+            Ok(actions) => {
+                println!("{}\n", start);
+                for action in actions {
+                    println!("{}", action);
+                }
+            }
+            Err(error) => println!("No plan found: {:?}", error),
+        }
+        return;
+    }
 
-    for action in actions {
-        execute_action_b(&mut piles, &action);
+    if model.is_none() && step.is_none() {
+        let answer_a = get_answer(open_input(&input_path), &CrateMover9000, trace).unwrap();
+        let answer_b = get_answer(open_input(&input_path), &CrateMover9001, trace).unwrap();
+        println!("Part 1: {}", answer_a);
+        println!("Part 2: {}", answer_b);
+        return;
     }
 
-    get_top_crates(&piles)
+    //Run the moves that can be done and show where the simulation ended up, or any earlier step
+    let model = model.unwrap_or(CraneModel::CrateMover9000);
+    let crane = model.build();
+    let mut reader = open_input(&input_path);
+    let piles = read_init_piles(&mut reader).unwrap();
+    let actions: Vec<Action> = ActionReader::new(reader).collect::<Result<_, _>>().unwrap();
+
+    let mut executor = Executor::new(piles, crane.as_ref());
+    if let Err(error) = run_actions(&mut executor, &actions, trace) {
+        println!(
+            "Stopped after {} of {} moves: {:?}",
+            executor.step(),
+            actions.len(),
+            error
+        );
+    }
+    if let Some(step) = step {
+        executor.replay_to(step).unwrap();
+        match executor.history().nth(step.wrapping_sub(1)) {
+            Some(action) => println!("After move {} {:?}", step, action),
+            None => println!("Before any move:"),
+        }
+        println!("{}\n", executor.piles());
+    }
+    println!("{:?}: {}", model, get_top_crates(executor.piles()));
 }
 
-fn main() {
-    let input_path = Path::new("./input/input.txt");
-    let input = read_to_string(input_path).expect("Expecting valid input file");
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    const TEST_INPUT: &str = "    [D]    \n\
+                              [N] [C]    \n\
+                              [Z] [M] [P]\n\
+                              \x201   2   3 \n\
+                              \n\
+                              move 1 from 2 to 1\n\
+                              move 3 from 1 to 3\n\
+                              move 2 from 2 to 1\n\
+                              move 1 from 1 to 2\n";
+
+    fn pile_contents(piles: &Piles) -> Vec<Vec<MyCrate>> {
+        piles.piles.to_vec()
+    }
+
+    #[test]
+    fn test_get_answer_a() {
+        assert_eq!(
+            get_answer(TEST_INPUT.as_bytes(), &CrateMover9000, false),
+            Ok("CMZ".to_string())
+        );
+    }
 
-    println!("Part 1: {}", get_answer_a(&input));
-    println!("Part 2: {}", get_answer_b(&input));
+    #[test]
+    fn test_get_answer_b() {
+        assert_eq!(
+            get_answer(TEST_INPUT.as_bytes(), &CrateMover9001, false),
+            Ok("MCD".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_init_piles_trimmed_lines() {
+        let drawing = "    [D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3";
+        let piles = parse_init_piles(drawing).unwrap();
+        assert_eq!(
+            pile_contents(&piles),
+            vec![vec!["Z", "N"], vec!["M", "C", "D"], vec!["P"]]
+        );
+    }
+
+    #[test]
+    fn test_parse_init_piles_wide_labels() {
+        let drawing = "       [CC]\n[AB]   [D]\n[E]    [FGH]\n 1      2";
+        let piles = parse_init_piles(drawing).unwrap();
+        assert_eq!(
+            pile_contents(&piles),
+            vec![vec!["E", "AB"], vec!["FGH", "D", "CC"]]
+        );
+    }
+
+    #[test]
+    fn test_render_piles_round_trip() {
+        let (drawing, _) = TEST_INPUT.split_once("\n\n").unwrap();
+        let piles = parse_init_piles(drawing).unwrap();
+        assert_eq!(piles.to_string(), drawing);
+
+        let wide_drawing = "       [CC]\n[AB]   [D]\n[E]    [FGH]\n 1      2";
+        let rendered = parse_init_piles(wide_drawing).unwrap().to_string();
+        assert_eq!(
+            rendered,
+            "      [CC] \n[AB]   [D] \n [E]  [FGH]\n  1     2  "
+        );
+        assert_eq!(
+            pile_contents(&parse_init_piles(&rendered).unwrap()),
+            pile_contents(&parse_init_piles(wide_drawing).unwrap())
+        );
+
+        //Short crates next to much wider ones, on piles numbered with one and two digits
+        let drawing = "[A]     [ABCDE]\n 1         2";
+        let mixed = Piles {
+            piles: (0..10)
+                .map(|i| {
+                    (0..i % 3 + 1)
+                        .map(|height| "ABCDEFGHIJK"[..(i * 7 + height) % 11 + 1].to_string())
+                        .collect()
+                })
+                .collect(),
+        };
+        for piles in [parse_init_piles(drawing).unwrap(), mixed] {
+            let rendered = piles.to_string();
+            assert_eq!(
+                parse_init_piles(&rendered).map(|piles| pile_contents(&piles)),
+                Ok(pile_contents(&piles)),
+                "{}",
+                rendered
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_move_list() {
+        let input = "[A]\n[B] [C]\n 1   2\n\nmove 1 from 1 to 2\nmove 3 from 2 to 1\n";
+        assert_eq!(
+            get_answer(input.as_bytes(), &CrateMover9000, false),
+            Err(SimulationError::Move(
+                2,
+                MoveError::NotEnoughCrates {
+                    pile_idx: 1,
+                    available: 2,
+                    requested: 3
+                }
+            ))
+        );
+
+        let input = "[A]\n 1\n\nmove 1 from 1 to 0\n";
+        assert!(matches!(
+            get_answer(input.as_bytes(), &CrateMover9000, false),
+            Err(SimulationError::Parsing(ParsingError::Action(1, _)))
+        ));
+    }
+
+    #[test]
+    fn test_parse_init_piles_errors() {
+        assert!(matches!(
+            parse_init_piles("[A] [B]\n 1   x"),
+            Err(ParsingError::Footer(_))
+        ));
+        assert!(matches!(
+            parse_init_piles("[A] [B\n 1   2"),
+            Err(ParsingError::CrateLine(1, _))
+        ));
+        assert!(matches!(
+            parse_init_piles("[A]\n    [B]\n 1   2"),
+            Err(ParsingError::CrateLine(1, _))
+        ));
+        assert!(matches!(
+            parse_init_piles("[A]     [B]\n 1   2"),
+            Err(ParsingError::CrateLine(1, _))
+        ));
+    }
 }
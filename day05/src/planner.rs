@@ -0,0 +1,210 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::crane::Crane;
+use crate::{Action, MyCrate, Pile, Piles};
+
+type State = Vec<Pile>;
+//For each configuration reached: the number of moves to get there and the move it came from
+type Reached = HashMap<State, (usize, Option<(State, Action)>)>;
+
+#[derive(Debug, PartialEq)]
+pub enum PlanError {
+    //The two drawings do not have the same number of piles or the same crates
+    Mismatch(String),
+    //Every configuration reachable from the start was explored without finding the target
+    Infeasible,
+    //The search gave up after exploring this many configurations
+    SearchLimit(usize),
+}
+
+fn to_state(piles: &Piles) -> State {
+    piles.piles.clone()
+}
+
+fn apply(crane: &dyn Crane, state: &State, action: &Action) -> State {
+    let mut piles = Piles {
+        piles: state.clone(),
+    };
+    crane
+        .execute(&mut piles, action)
+        .expect("Expecting the planner to only try possible moves");
+    piles.piles
+}
+
+/// Lower bound on the number of moves left. Every pile that differs from its target has to be
+/// touched by a move, and a move touches at most two piles. Cranes only ever add crates on top, so
+/// a pile that is not the bottom of its target also needs its own move taking crates away.
+fn heuristic(state: &State, target: &State) -> usize {
+    let mut n_different: usize = 0;
+    let mut n_to_unload = 0;
+    for (pile, target_pile) in state.iter().zip(target) {
+        if pile != target_pile {
+            n_different += 1;
+        }
+        if !target_pile.starts_with(pile) {
+            n_to_unload += 1;
+        }
+    }
+    n_to_unload.max(n_different.div_ceil(2))
+}
+
+fn sorted_crates(state: &State) -> Vec<&MyCrate> {
+    let mut crates: Vec<_> = state.iter().flatten().collect();
+    crates.sort();
+    crates
+}
+
+/// Searches with A* for a shortest list of actions bringing the `start` piles to the `target` ones
+/// with the given crane. At most `max_states` configurations are explored.
+pub fn plan(
+    start: &Piles,
+    target: &Piles,
+    crane: &dyn Crane,
+    max_states: usize,
+) -> Result<Vec<Action>, PlanError> {
+    let start = to_state(start);
+    let target = to_state(target);
+    if start.len() != target.len() {
+        return Err(PlanError::Mismatch(format!(
+            "Expected {} piles in the target but found {}",
+            start.len(),
+            target.len()
+        )));
+    }
+    if sorted_crates(&start) != sorted_crates(&target) {
+        return Err(PlanError::Mismatch(
+            "Expected the target to hold the same crates as the start".to_string(),
+        ));
+    }
+
+    let mut reached: Reached = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    //States are kept in a side vector so that the heap only orders on numbers
+    let mut states = vec![start.clone()];
+    reached.insert(start.clone(), (0, None));
+    queue.push(Reverse((heuristic(&start, &target), 0, 0)));
+
+    let mut n_explored = 0;
+    while let Some(Reverse((_, moves, state_idx))) = queue.pop() {
+        let state = states[state_idx].clone();
+        if reached[&state].0 < moves {
+            continue;
+        }
+        if state == target {
+            return Ok(rebuild_path(&reached, state));
+        }
+
+        n_explored += 1;
+        if n_explored > max_states {
+            return Err(PlanError::SearchLimit(max_states));
+        }
+
+        for from_pile in 0..state.len() {
+            for to_pile in (0..state.len()).filter(|&to_pile| to_pile != from_pile) {
+                for crates_to_move in 1..=state[from_pile].len() {
+                    let action = Action {
+                        crates_to_move,
+                        from_pile,
+                        to_pile,
+                    };
+                    let next = apply(crane, &state, &action);
+                    if reached
+                        .get(&next)
+                        .is_some_and(|&(known_moves, _)| known_moves <= moves + 1)
+                    {
+                        continue;
+                    }
+
+                    let estimate = moves + 1 + heuristic(&next, &target);
+                    reached.insert(next.clone(), (moves + 1, Some((state.clone(), action))));
+                    queue.push(Reverse((estimate, moves + 1, states.len())));
+                    states.push(next);
+                }
+            }
+        }
+    }
+
+    Err(PlanError::Infeasible)
+}
+
+fn rebuild_path(reached: &Reached, mut state: State) -> Vec<Action> {
+    let mut actions = Vec::new();
+    while let Some((previous, action)) = &reached[&state].1 {
+        actions.push(*action);
+        state = previous.clone();
+    }
+    actions.reverse();
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crane::{BottomLiftCrane, CrateMover9000, CrateMover9001};
+    use crate::parse_init_piles;
+
+    fn check_plan(start: &str, target: &str, crane: &dyn Crane, expected_moves: usize) {
+        let start = parse_init_piles(start).unwrap();
+        let target = parse_init_piles(target).unwrap();
+        let actions = plan(&start, &target, crane, 100_000).unwrap();
+        assert_eq!(actions.len(), expected_moves);
+
+        let mut state = to_state(&start);
+        for action in actions.iter() {
+            state = apply(crane, &state, action);
+        }
+        assert_eq!(state, to_state(&target));
+    }
+
+    #[test]
+    fn test_plan() {
+        let start = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 ";
+        check_plan(start, start, &CrateMover9000, 0);
+        check_plan(
+            start,
+            "[D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3",
+            &CrateMover9000,
+            1,
+        );
+        check_plan(
+            start,
+            "[C]\n[D]\n[N]\n[Z] [M] [P]\n 1   2   3",
+            &CrateMover9000,
+            1,
+        );
+        check_plan(
+            start,
+            "[C]\n[D]\n[N]\n[Z] [M] [P]\n 1   2   3",
+            &CrateMover9001,
+            2,
+        );
+        check_plan(
+            start,
+            "[D]\n[C]\n[M]\n[N]\n[Z]     [P]\n 1   2   3",
+            &BottomLiftCrane,
+            1,
+        );
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        let start = parse_init_piles("[A] [B]\n 1   2").unwrap();
+        let swapped = parse_init_piles("[B] [A]\n 1   2").unwrap();
+        let other = parse_init_piles("[A] [C]\n 1   2").unwrap();
+
+        //Swapping two crates needs a free pile
+        assert_eq!(
+            plan(&start, &swapped, &CrateMover9000, 1000),
+            Err(PlanError::Infeasible)
+        );
+        assert!(matches!(
+            plan(&start, &other, &CrateMover9000, 1000),
+            Err(PlanError::Mismatch(_))
+        ));
+        assert_eq!(
+            plan(&start, &swapped, &CrateMover9001, 1),
+            Err(PlanError::SearchLimit(1))
+        );
+    }
+}
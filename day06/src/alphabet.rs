@@ -0,0 +1,263 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use crate::marker::MarkerWindow;
+
+/// Anything a datastream can be made of, like bytes or Unicode scalars.
+pub trait Symbol: Copy + Eq + Hash + Debug {
+    /// The symbol as shown in messages.
+    fn describe(self) -> String;
+}
+
+impl Symbol for u8 {
+    fn describe(self) -> String {
+        if self.is_ascii_graphic() {
+            format!("'{}'", self as char)
+        } else {
+            format!("0x{:02x}", self)
+        }
+    }
+}
+
+impl Symbol for char {
+    fn describe(self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// The symbols a datastream can contain, each mapped to a small index.
+pub struct Alphabet<S: Symbol> {
+    indices: HashMap<S, usize>,
+}
+
+impl<S: Symbol> Alphabet<S> {
+    pub fn new(symbols: impl IntoIterator<Item = S>) -> Self {
+        let mut indices = HashMap::new();
+        for symbol in symbols {
+            let next_index = indices.len();
+            indices.entry(symbol).or_insert(next_index);
+        }
+        Alphabet { indices }
+    }
+
+    /// The alphabet made of the symbols found in the datastream.
+    pub fn detect(symbols: &[S]) -> Self {
+        Alphabet::new(symbols.iter().copied())
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MarkerError<S: Symbol> {
+    //Offset in symbols of the first symbol that is not part of the alphabet
+    UnknownSymbol { offset: usize, symbol: S },
+}
+
+impl<S: Symbol> std::fmt::Display for MarkerError<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarkerError::UnknownSymbol { offset, symbol } => write!(
+                f,
+                "Symbol {} at offset {} is not part of the alphabet",
+                symbol.describe(),
+                offset
+            ),
+        }
+    }
+}
+
+/// A set of alphabet indices packed in bits, usable when the alphabet is small enough.
+trait BitSet {
+    fn empty() -> Self;
+    fn contains(&self, index: usize) -> bool;
+    /// Adds the index if it is missing, removes it otherwise.
+    fn toggle(&mut self, index: usize);
+}
+
+impl BitSet for u128 {
+    fn empty() -> Self {
+        0
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self & (1 << index) != 0
+    }
+
+    fn toggle(&mut self, index: usize) {
+        *self ^= 1 << index;
+    }
+}
+
+//No u256 in std, so two u128 next to each other
+impl BitSet for [u128; 2] {
+    fn empty() -> Self {
+        [0; 2]
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self[index / 128].contains(index % 128)
+    }
+
+    fn toggle(&mut self, index: usize) {
+        self[index / 128].toggle(index % 128)
+    }
+}
+
+/// A window fed one alphabet index at a time, telling whether it ends a marker.
+trait SlidingWindow {
+    fn push_index(&mut self, index: usize) -> bool;
+}
+
+impl SlidingWindow for MarkerWindow {
+    fn push_index(&mut self, index: usize) -> bool {
+        MarkerWindow::push_index(self, index)
+    }
+}
+
+/// The longest run of distinct symbols ending at the last one, at most `marker_size` long. A
+/// repeated symbol drops everything up to its first copy, so each symbol enters and leaves once.
+struct BitSetWindow<B: BitSet> {
+    marker_size: usize,
+    window: VecDeque<usize>,
+    seen: B,
+}
+
+impl<B: BitSet> BitSetWindow<B> {
+    fn new(marker_size: usize) -> Self {
+        BitSetWindow {
+            marker_size,
+            window: VecDeque::with_capacity(marker_size + 1),
+            seen: B::empty(),
+        }
+    }
+}
+
+impl<B: BitSet> SlidingWindow for BitSetWindow<B> {
+    fn push_index(&mut self, index: usize) -> bool {
+        while self.seen.contains(index) || self.window.len() == self.marker_size {
+            let leaving = self.window.pop_front().unwrap();
+            self.seen.toggle(leaving);
+        }
+        self.window.push_back(index);
+        self.seen.toggle(index);
+        self.window.len() == self.marker_size
+    }
+}
+
+fn scan<S: Symbol>(
+    symbols: &[S],
+    alphabet: &Alphabet<S>,
+    marker_size: usize,
+    mut window: impl SlidingWindow,
+) -> Result<Option<usize>, MarkerError<S>> {
+    //Without enough different symbols, the window is never a marker
+    let can_match = marker_size > 0 && marker_size <= alphabet.len();
+    let mut marker = (marker_size == 0).then_some(0);
+    for (offset, symbol) in symbols.iter().enumerate() {
+        let index = *alphabet
+            .indices
+            .get(symbol)
+            .ok_or(MarkerError::UnknownSymbol {
+                offset,
+                symbol: *symbol,
+            })?;
+        if can_match && marker.is_none() && window.push_index(index) {
+            marker = Some(offset + 1);
+        }
+    }
+    Ok(marker)
+}
+
+/// Finds the end of the first `marker_size` distinct symbols, as an offset in symbols, in linear
+/// time. Alphabets of up to 256 symbols keep the window in a bitset, bigger ones in a table of
+/// counts. Every symbol is checked against the alphabet, even after the marker.
+pub fn find_marker<S: Symbol>(
+    symbols: &[S],
+    alphabet: &Alphabet<S>,
+    marker_size: usize,
+) -> Result<Option<usize>, MarkerError<S>> {
+    match alphabet.len() {
+        0..=128 => scan(
+            symbols,
+            alphabet,
+            marker_size,
+            BitSetWindow::<u128>::new(marker_size),
+        ),
+        129..=256 => scan(
+            symbols,
+            alphabet,
+            marker_size,
+            BitSetWindow::<[u128; 2]>::new(marker_size),
+        ),
+        alphabet_size => scan(
+            symbols,
+            alphabet,
+            marker_size,
+            MarkerWindow::with_alphabet_size(marker_size, alphabet_size),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_marker_strategies() {
+        //Alphabets small enough for one or two u128, and too big for them
+        for alphabet_size in [26, 128, 129, 256, 300, 1000] {
+            let alphabet =
+                Alphabet::new((0..alphabet_size).map(|i| char::from_u32(0x4E00 + i).unwrap()));
+            let n_used = alphabet_size.min(256);
+            let input: Vec<char> = [0, 1, 0]
+                .into_iter()
+                .chain(0..n_used)
+                .chain(0..n_used)
+                .map(|i| char::from_u32(0x4E00 + i).unwrap())
+                .collect();
+            let n_used = n_used as usize;
+            assert_eq!(find_marker(&input, &alphabet, 3), Ok(Some(6)));
+            assert_eq!(find_marker(&input, &alphabet, n_used), Ok(Some(n_used + 3)));
+            assert_eq!(find_marker(&input, &alphabet, n_used + 1), Ok(None));
+        }
+
+        let alphabet = Alphabet::new(b"abcdefghijklmnopqrstuvwxyz".iter().copied());
+        let input = b"mjqjpqmgbljsphdztnvjfqwrcgsmlb";
+        assert_eq!(find_marker(input, &alphabet, 4), Ok(Some(7)));
+        assert_eq!(find_marker(input, &alphabet, 14), Ok(Some(19)));
+        assert_eq!(find_marker(input, &alphabet, 27), Ok(None));
+        assert_eq!(find_marker(input, &alphabet, 0), Ok(Some(0)));
+        assert_eq!(find_marker(b"abcabcabcabcabcd", &alphabet, 4), Ok(Some(16)));
+    }
+
+    #[test]
+    fn test_find_marker_unicode() {
+        let chars: Vec<char> = "ééàéçàü".chars().collect();
+        let alphabet = Alphabet::detect(&chars);
+        assert_eq!(alphabet.len(), 4);
+        assert_eq!(find_marker(&chars, &alphabet, 3), Ok(Some(5)));
+        assert_eq!(find_marker(&chars, &alphabet, 4), Ok(Some(7)));
+        assert_eq!(find_marker(&chars, &alphabet, 5), Ok(None));
+    }
+
+    #[test]
+    fn test_find_marker_unknown_symbol() {
+        let alphabet = Alphabet::new(b"abc".iter().copied());
+        assert_eq!(
+            find_marker(b"abcAB", &alphabet, 2),
+            Err(MarkerError::UnknownSymbol {
+                offset: 3,
+                symbol: b'A'
+            })
+        );
+        let error = find_marker(b"ab\x7f", &alphabet, 2).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Symbol 0x7f at offset 2 is not part of the alphabet"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt::Write;
+
+use crate::marker::MarkerWindow;
+use crate::{START_OF_MESAGE_SIZE, START_OF_PACKET_SIZE};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FrameKind {
+    Packet,
+    Message,
+}
+
+/// A marker and the payload following it, up to the next marker or the end of the datastream.
+#[derive(Debug, PartialEq)]
+pub struct Frame<'a> {
+    pub kind: FrameKind,
+    pub marker_offset: usize,
+    pub payload_offset: usize,
+    pub payload: &'a [u8],
+}
+
+/// A datastream split in frames. The preamble is whatever comes before the first marker.
+#[derive(Debug, PartialEq)]
+pub struct Segmentation<'a> {
+    pub preamble: &'a [u8],
+    pub frames: Vec<Frame<'a>>,
+}
+
+/// Finds where the first marker of the given size in the bytes ends.
+fn search_bytes(bytes: &[u8], marker_size: usize) -> Option<usize> {
+    let mut window = MarkerWindow::new(marker_size);
+    bytes
+        .iter()
+        .position(|&byte| window.push(byte))
+        .map(|i| i + 1)
+}
+
+/// Splits the datastream in frames, each marker starting a new one. Markers never overlap: the
+/// search for the next one starts right after the previous one.
+///
+/// A start-of-message marker always contains a start-of-packet one. When the next start-of-packet
+/// marker is part of the next start-of-message marker, the whole is a start-of-message marker.
+pub fn segment(input: &[u8]) -> Segmentation<'_> {
+    //Each marker as its kind and the offsets where it starts and ends
+    let mut markers: Vec<(FrameKind, usize, usize)> = Vec::new();
+    //The next start-of-message marker stays the same until the search starts past its beginning
+    let mut message_end: Option<Option<usize>> = None;
+    let mut from = 0;
+
+    while let Some(packet_end) = search_bytes(&input[from..], START_OF_PACKET_SIZE) {
+        let packet_start = from + packet_end - START_OF_PACKET_SIZE;
+        if message_end.is_some_and(|end| end.is_some_and(|end| end - START_OF_MESAGE_SIZE < from)) {
+            message_end = None;
+        }
+        let message_end = *message_end.get_or_insert_with(|| {
+            search_bytes(&input[from..], START_OF_MESAGE_SIZE).map(|end| from + end)
+        });
+
+        let marker = match message_end {
+            Some(message_end) if packet_start >= message_end - START_OF_MESAGE_SIZE => (
+                FrameKind::Message,
+                message_end - START_OF_MESAGE_SIZE,
+                message_end,
+            ),
+            _ => (FrameKind::Packet, packet_start, from + packet_end),
+        };
+        markers.push(marker);
+        from = marker.2;
+    }
+
+    let preamble_end = markers.first().map_or(input.len(), |marker| marker.1);
+    let frames = markers
+        .iter()
+        .enumerate()
+        .map(|(i, &(kind, marker_offset, payload_offset))| {
+            let payload_end = markers.get(i + 1).map_or(input.len(), |next| next.1);
+            Frame {
+                kind,
+                marker_offset,
+                payload_offset,
+                payload: &input[payload_offset..payload_end],
+            }
+        })
+        .collect();
+
+    Segmentation {
+        preamble: &input[..preamble_end],
+        frames,
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut output = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => output += "\\\"",
+            '\\' => output += "\\\\",
+            '\n' => output += "\\n",
+            c if c.is_control() => write!(output, "\\u{:04x}", c as u32).unwrap(),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
+
+/// A JSON field holding bytes, as a string when they are UTF-8. Other bytes go in hexadecimal, under
+/// the key followed by `_hex`, so that they can be read back exactly.
+fn json_bytes(key: &str, bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => format!("\"{}\": {}", key, json_string(text)),
+        Err(_) => {
+            let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("\"{}_hex\": \"{}\"", key, hex)
+        }
+    }
+}
+
+impl Segmentation<'_> {
+    pub fn to_json(&self) -> String {
+        let frames: Vec<String> = self
+            .frames
+            .iter()
+            .map(|frame| {
+                format!(
+                    "    {{\"kind\": \"{}\", \"marker_offset\": {}, \"payload_offset\": {}, \"length\": {}, {}}}",
+                    match frame.kind {
+                        FrameKind::Packet => "packet",
+                        FrameKind::Message => "message",
+                    },
+                    frame.marker_offset,
+                    frame.payload_offset,
+                    frame.payload.len(),
+                    json_bytes("payload", frame.payload)
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\n  {},\n  \"frames\": [\n{}\n  ]\n}}",
+            json_bytes("preamble", self.preamble),
+            frames.join(",\n")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_packets() {
+        let segmentation = segment(b"aaabcdxxxxefghyyyy");
+        assert_eq!(segmentation.preamble, b"aa");
+        assert_eq!(
+            segmentation.frames,
+            vec![
+                Frame {
+                    kind: FrameKind::Packet,
+                    marker_offset: 2,
+                    payload_offset: 6,
+                    payload: b"xxx",
+                },
+                Frame {
+                    kind: FrameKind::Packet,
+                    marker_offset: 9,
+                    payload_offset: 13,
+                    payload: b"hyyyy",
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_segment_message() {
+        let segmentation = segment(b"aaabcdefghijklmnzz");
+        assert_eq!(segmentation.preamble, b"aa");
+        assert_eq!(
+            segmentation.frames,
+            vec![Frame {
+                kind: FrameKind::Message,
+                marker_offset: 2,
+                payload_offset: 16,
+                payload: b"zz",
+            }]
+        );
+
+        let segmentation = segment(b"zzzz");
+        assert_eq!(segmentation.preamble, b"zzzz");
+        assert!(segmentation.frames.is_empty());
+    }
+
+    #[test]
+    fn test_segment_reuses_message_marker() {
+        //The start-of-message marker is found while looking for the first frame, and still has to
+        //win over the start-of-packet marker it contains after a few packets
+        let mut input = b"aaaabcdaaaa".to_vec();
+        input.extend(b"zzabcdefghijklmnzz");
+        let kinds: Vec<_> = segment(&input)
+            .frames
+            .iter()
+            .map(|frame| (frame.kind, frame.marker_offset))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![(FrameKind::Packet, 3), (FrameKind::Message, 12)]
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = segment(b"a\"abc\"\n").to_json();
+        assert_eq!(
+            json,
+            "{\n  \"preamble\": \"a\",\n  \"frames\": [\n    \
+             {\"kind\": \"packet\", \"marker_offset\": 1, \"payload_offset\": 5, \"length\": 2, \"payload\": \"\\\"\\n\"}\n  ]\n}"
+        );
+
+        //Bytes that are not UTF-8 are kept as they are, in hexadecimal
+        let json = segment(b"\xff\xffabc\xfe\x00").to_json();
+        assert_eq!(
+            json,
+            "{\n  \"preamble_hex\": \"ff\",\n  \"frames\": [\n    \
+             {\"kind\": \"packet\", \"marker_offset\": 1, \"payload_offset\": 5, \"length\": 2, \"payload_hex\": \"fe00\"}\n  ]\n}"
+        );
+    }
+}
@@ -1,44 +1,132 @@
+mod alphabet;
+mod frames;
+mod marker;
+
+use alphabet::{find_marker, Alphabet, MarkerError, Symbol};
+use marker::{MarkerDetector, MarkerEvent, MarkerWindow};
 use std::fs::read_to_string;
 use std::path::Path;
 use std::println;
 
-fn search_for_marker(input: &str, marker_size: usize) -> Option<usize> {
-    let bytes = input.as_bytes();
-
-    for i in 0..bytes.len() - marker_size {
-        let slice = &bytes[i..i + marker_size];
-
-        //The characters are a..z, for a total of 26 characters.
-        //That means we can assign each characters to a bit in a u32
-        //We can the do an or for between all the characters and the number of ones will be the
-        //number of unique characters
-        let ones = slice.iter().map(|byte| 1_u32 << (byte - b'a'));
-        let number_of_ones: u32 = ones.fold(0, |acc: u32, byte: u32| acc | byte).count_ones();
+const START_OF_PACKET_SIZE: usize = 4;
+const START_OF_MESAGE_SIZE: usize = 14;
 
-        if number_of_ones == marker_size as u32 {
-            return Some(i + marker_size);
-        }
+/// Searches a datastream of any bytes.
+fn search_for_marker(input: &str, marker_size: usize) -> Option<usize> {
+    if marker_size == 0 {
+        return Some(0);
     }
+    let mut window = MarkerWindow::new(marker_size);
+    input
+        .bytes()
+        .position(|byte| window.push(byte))
+        .map(|offset| offset + 1)
+}
 
-    None
+/// Searches with a given alphabet, or the detected one when there is none.
+fn search_with_alphabet<S: Symbol>(
+    symbols: &[S],
+    alphabet: Option<&[S]>,
+    marker_size: usize,
+) -> Result<Option<usize>, MarkerError<S>> {
+    let alphabet = match alphabet {
+        Some(alphabet) => Alphabet::new(alphabet.iter().copied()),
+        None => Alphabet::detect(symbols),
+    };
+    find_marker(symbols, &alphabet, marker_size)
 }
 
 fn get_answer_a(input: &str) -> Option<usize> {
-    const START_OF_PACKET_SIZE: usize = 4;
     search_for_marker(input, START_OF_PACKET_SIZE)
 }
 
 fn get_answer_b(input: &str) -> Option<usize> {
-    const START_OF_MESAGE_SIZE: usize = 14;
     search_for_marker(input, START_OF_MESAGE_SIZE)
 }
 
 fn main() {
+    //Usage: day6 [--stream | --frames | --json | [--unicode] [--alphabet <symbols>]]
+    //With --stream, the datastream is read from stdin and the markers are reported as they arrive.
+    //With --unicode, markers are searched among characters instead of bytes, and offsets count
+    //characters. With --frames or --json, the datastream is split in frames, one per marker.
+    let mut stream = false;
+    let mut frames = false;
+    let mut json = false;
+    let mut unicode = false;
+    let mut alphabet: Option<String> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stream" => stream = true,
+            "--frames" => frames = true,
+            "--json" => json = true,
+            "--unicode" => unicode = true,
+            "--alphabet" => {
+                alphabet = Some(args.next().expect("Expecting symbols after --alphabet"))
+            }
+            arg => panic!("Unknown argument: {}", arg),
+        }
+    }
+
+    if stream {
+        let mut detector = MarkerDetector::new();
+        detector
+            .read_from(std::io::stdin().lock(), |event| match event {
+                MarkerEvent::StartOfPacket(offset) => println!("Start of packet: {}", offset),
+                MarkerEvent::StartOfMessage(offset) => println!("Start of message: {}", offset),
+            })
+            .expect("Expecting a readable datastream");
+        return;
+    }
+
     let input_path = Path::new("./input/input.txt");
     let input = read_to_string(input_path).expect("Expecting valid input file");
 
-    println!("Part 1: {}", get_answer_a(&input).unwrap());
-    println!("Part 2: {}", get_answer_b(&input).unwrap());
+    //The line break at the end of the file is not part of the datastream, nor of a given alphabet
+    let input = input.trim_end();
+    if frames || json {
+        let segmentation = frames::segment(input.as_bytes());
+        if json {
+            println!("{}", segmentation.to_json());
+            return;
+        }
+        println!("Preamble of {} bytes", segmentation.preamble.len());
+        for frame in segmentation.frames {
+            println!(
+                "{:?} at {}: {} bytes from {}",
+                frame.kind,
+                frame.marker_offset,
+                frame.payload.len(),
+                frame.payload_offset
+            );
+        }
+        return;
+    }
+    if unicode || alphabet.is_some() {
+        for (part, marker_size) in [(1, START_OF_PACKET_SIZE), (2, START_OF_MESAGE_SIZE)] {
+            let answer = if unicode {
+                let chars: Vec<char> = input.chars().collect();
+                let alphabet: Option<Vec<char>> = alphabet.as_ref().map(|a| a.chars().collect());
+                search_with_alphabet(&chars, alphabet.as_deref(), marker_size)
+                    .map_err(|error| error.to_string())
+            } else {
+                let alphabet = alphabet.as_ref().map(|alphabet| alphabet.as_bytes());
+                search_with_alphabet(input.as_bytes(), alphabet, marker_size)
+                    .map_err(|error| error.to_string())
+            };
+            match answer {
+                Ok(answer) => println!("Part {}: {}", part, answer.unwrap()),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        return;
+    }
+
+    println!("Part 1: {}", get_answer_a(input).unwrap());
+    println!("Part 2: {}", get_answer_b(input).unwrap());
 }
 
 #[cfg(test)]
@@ -64,4 +152,39 @@ mod tests {
         assert_eq!(get_answer_b("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg"), Some(29));
         assert_eq!(get_answer_b("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw"), Some(26));
     }
+
+    #[test]
+    fn test_search_for_marker_at_the_end() {
+        assert_eq!(search_for_marker("aabc", 3), Some(4));
+        assert_eq!(search_for_marker("abcd", 4), Some(4));
+        assert_eq!(search_for_marker("aabcb", 3), Some(4));
+        assert_eq!(search_for_marker("aaab", 3), None);
+    }
+
+    #[test]
+    fn test_search_with_alphabet() {
+        let chars: Vec<char> = "aébcaé".chars().collect();
+        assert_eq!(search_with_alphabet(&chars, None, 4), Ok(Some(4)));
+        let alphabet: Vec<char> = "abc".chars().collect();
+        let error = search_with_alphabet(&chars, Some(&alphabet), 4).unwrap_err();
+        assert_eq!(
+            error,
+            MarkerError::UnknownSymbol {
+                offset: 1,
+                symbol: 'é'
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Symbol 'é' at offset 1 is not part of the alphabet"
+        );
+    }
+
+    #[test]
+    fn test_search_for_marker_short_input() {
+        assert_eq!(search_for_marker("abc", 4), None);
+        assert_eq!(search_for_marker("", 4), None);
+        assert_eq!(search_for_marker("", 0), Some(0));
+        assert_eq!(search_for_marker("a", 1), Some(1));
+    }
 }
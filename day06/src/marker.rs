@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::io::Read;
+
+use crate::{START_OF_MESAGE_SIZE, START_OF_PACKET_SIZE};
+
+/// The last `marker_size` symbols of a datastream, fed one symbol at a time as their index in an
+/// alphabet. Bytes are their own index.
+pub struct MarkerWindow {
+    marker_size: usize,
+    window: VecDeque<usize>,
+    //How many times each symbol appears in the window, and how many different symbols appear more
+    //than once. The window is a marker when it is full and there are none.
+    counts: Vec<usize>,
+    n_repeated: usize,
+}
+
+impl MarkerWindow {
+    pub fn new(marker_size: usize) -> Self {
+        MarkerWindow::with_alphabet_size(marker_size, 256)
+    }
+
+    pub fn with_alphabet_size(marker_size: usize, alphabet_size: usize) -> Self {
+        MarkerWindow {
+            marker_size,
+            window: VecDeque::with_capacity(marker_size + 1),
+            counts: vec![0; alphabet_size],
+            n_repeated: 0,
+        }
+    }
+
+    /// Slides the window by one byte and tells whether it is now a marker.
+    pub fn push(&mut self, byte: u8) -> bool {
+        self.push_index(byte as usize)
+    }
+
+    /// Slides the window by one symbol of the alphabet and tells whether it is now a marker.
+    pub fn push_index(&mut self, index: usize) -> bool {
+        self.window.push_back(index);
+        self.counts[index] += 1;
+        if self.counts[index] == 2 {
+            self.n_repeated += 1;
+        }
+
+        if self.window.len() > self.marker_size {
+            let leaving = self.window.pop_front().unwrap();
+            self.counts[leaving] -= 1;
+            if self.counts[leaving] == 1 {
+                self.n_repeated -= 1;
+            }
+        }
+
+        self.window.len() == self.marker_size && self.n_repeated == 0
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MarkerEvent {
+    //Offsets are counted from the start of the stream, and point right after the marker
+    StartOfPacket(usize),
+    StartOfMessage(usize),
+}
+
+/// Looks for the start-of-packet and start-of-message markers in a datastream that arrives in
+/// chunks. Each marker is reported once, at the same offset as if the whole stream was searched
+/// at once.
+pub struct MarkerDetector {
+    offset: usize,
+    packet: Option<MarkerWindow>,
+    message: Option<MarkerWindow>,
+}
+
+impl MarkerDetector {
+    pub fn new() -> Self {
+        MarkerDetector {
+            offset: 0,
+            packet: Some(MarkerWindow::new(START_OF_PACKET_SIZE)),
+            message: Some(MarkerWindow::new(START_OF_MESAGE_SIZE)),
+        }
+    }
+
+    /// Whether both markers were found, after which the rest of the stream does not matter.
+    pub fn is_done(&self) -> bool {
+        self.packet.is_none() && self.message.is_none()
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<MarkerEvent> {
+        let mut events = Vec::new();
+        for &byte in chunk {
+            self.offset += 1;
+            //A window is dropped once its marker is found, only the first one counts
+            if self.packet.as_mut().is_some_and(|window| window.push(byte)) {
+                self.packet = None;
+                events.push(MarkerEvent::StartOfPacket(self.offset));
+            }
+            if self
+                .message
+                .as_mut()
+                .is_some_and(|window| window.push(byte))
+            {
+                self.message = None;
+                events.push(MarkerEvent::StartOfMessage(self.offset));
+            }
+        }
+        events
+    }
+
+    /// Feeds the detector from a reader until both markers are found or the reader is exhausted.
+    pub fn read_from<R: Read>(
+        &mut self,
+        mut reader: R,
+        mut on_event: impl FnMut(MarkerEvent),
+    ) -> std::io::Result<()> {
+        let mut buffer = [0_u8; 4096];
+        while !self.is_done() {
+            let n_read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n_read) => n_read,
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            self.push(&buffer[..n_read])
+                .into_iter()
+                .for_each(&mut on_event);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_for_marker;
+
+    const TEST_INPUTS: [&str; 5] = [
+        "mjqjpqmgbljsphdztnvjfqwrcgsmlb",
+        "bvwbjplbgvbhsrlpgdmjqwftvncz",
+        "nppdvjthqldpwncqszvftbrmjlhg",
+        "nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg",
+        "zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw",
+    ];
+
+    fn expected_events(input: &str) -> Vec<MarkerEvent> {
+        let mut events = vec![
+            search_for_marker(input, START_OF_PACKET_SIZE).map(MarkerEvent::StartOfPacket),
+            search_for_marker(input, START_OF_MESAGE_SIZE).map(MarkerEvent::StartOfMessage),
+        ];
+        events.sort_by_key(|event| match event {
+            Some(MarkerEvent::StartOfPacket(offset)) => *offset,
+            Some(MarkerEvent::StartOfMessage(offset)) => *offset,
+            None => usize::MAX,
+        });
+        events.into_iter().flatten().collect()
+    }
+
+    #[test]
+    fn test_chunk_boundaries() {
+        for input in TEST_INPUTS {
+            let expected = expected_events(input);
+            for chunk_size in 1..=input.len() {
+                let mut detector = MarkerDetector::new();
+                let events: Vec<_> = input
+                    .as_bytes()
+                    .chunks(chunk_size)
+                    .flat_map(|chunk| detector.push(chunk))
+                    .collect();
+                assert_eq!(events, expected, "chunks of {} bytes", chunk_size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_from() {
+        let mut detector = MarkerDetector::new();
+        let mut events = Vec::new();
+        detector
+            .read_from(TEST_INPUTS[0].as_bytes(), |event| events.push(event))
+            .unwrap();
+        assert!(detector.is_done());
+        assert_eq!(
+            events,
+            vec![
+                MarkerEvent::StartOfPacket(7),
+                MarkerEvent::StartOfMessage(19)
+            ]
+        );
+
+        let mut detector = MarkerDetector::new();
+        let mut events = Vec::new();
+        detector
+            .read_from("abcabc".as_bytes(), |event| events.push(event))
+            .unwrap();
+        assert!(!detector.is_done());
+        assert!(events.is_empty());
+    }
+}
@@ -1,13 +1,26 @@
-use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::path::Path;
 use std::str::FromStr;
-use std::todo;
+
+mod materialize;
+mod reclaim;
+mod report;
+mod shell;
+mod transcript;
+mod vfs;
+
+use crate::reclaim::Objective;
+use crate::report::{ReportOptions, SortKey};
+use crate::transcript::{Order, TranscriptOptions};
+use crate::vfs::{FsError, VirtualFs};
 
 #[derive(Debug, PartialEq)]
 enum Commands {
     ChangeDirectory { path: Option<String> },
     List { list: Vec<ListTypes> },
+    Remove { path: String, recursive: bool },
+    //The path printed by `pwd`, if the transcript shows it
+    PrintDirectory { path: Option<String> },
 }
 
 #[derive(Debug, PartialEq)]
@@ -16,49 +29,270 @@ enum ListTypes {
     Dir { name: String },
 }
 
-#[derive(Debug)]
-struct ParseListTypeError;
+impl std::fmt::Display for ListTypes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListTypes::File { name, size } => write!(f, "{} {}", size, name),
+            ListTypes::Dir { name } => write!(f, "dir {}", name),
+        }
+    }
+}
+
+//Written back exactly as `Commands::from_str` reads it
+impl std::fmt::Display for Commands {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Commands::ChangeDirectory { path: Some(path) } => write!(f, "$ cd {}", path),
+            Commands::ChangeDirectory { path: None } => write!(f, "$ cd"),
+            Commands::List { list } => {
+                write!(f, "$ ls")?;
+                for entry in list {
+                    write!(f, "\n{}", entry)?;
+                }
+                Ok(())
+            }
+            Commands::Remove {
+                path,
+                recursive: true,
+            } => write!(f, "$ rm -r {}", path),
+            Commands::Remove {
+                path,
+                recursive: false,
+            } => write!(f, "$ rm {}", path),
+            Commands::PrintDirectory { path: Some(path) } => write!(f, "$ pwd\n{}", path),
+            Commands::PrintDirectory { path: None } => write!(f, "$ pwd"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ParseListTypeError {
+    Empty,
+    BadSize(String),
+    MissingName,
+    //A line of `ls -l` without the permissions, links, owner and group before the size
+    MissingFields,
+    //A symbolic link in `ls -l`, as `name -> target`, which the tree can not hold
+    Symlink(String),
+}
+
 impl std::str::FromStr for ListTypes {
     type Err = ParseListTypeError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut words = s.split_ascii_whitespace();
-        match words.next() {
-            Some("dir") => Ok(ListTypes::Dir {
-                name: words.next().ok_or(ParseListTypeError)?.to_string(),
-            }),
-            x => Ok(ListTypes::File {
-                size: match x.ok_or(ParseListTypeError)?.parse::<u32>() {
-                    Ok(size) => Ok(size),
-                    _ => Err(ParseListTypeError),
-                }?,
-                name: words.next().ok_or(ParseListTypeError)?.to_string(),
+        let first_word = words.next().ok_or(ParseListTypeError::Empty)?;
+        //Names can hold spaces, as written by `Display`
+        let name = words.collect::<Vec<_>>().join(" ");
+        if name.is_empty() {
+            return Err(ParseListTypeError::MissingName);
+        }
+        match first_word {
+            "dir" => Ok(ListTypes::Dir { name }),
+            size => Ok(ListTypes::File {
+                size: size
+                    .parse::<u32>()
+                    .map_err(|_| ParseListTypeError::BadSize(size.to_string()))?,
+                name,
             }),
         }
     }
 }
 
+impl ListTypes {
+    /// Reads a line of `ls -l`, like `-rw-r--r-- 1 user group 14848514 Dec 7 10:00 b.txt`. The
+    /// `total` line gives None.
+    fn from_long_str(s: &str) -> Result<Option<Self>, ParseListTypeError> {
+        let words: Vec<&str> = s.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            [] => Err(ParseListTypeError::Empty),
+            ["total", ..] => Ok(None),
+            [permissions, _, _, _, size, _, _, _, name @ ..] if !name.is_empty() => {
+                let name = name.join(" ");
+                if permissions.starts_with('l') {
+                    return Err(ParseListTypeError::Symlink(name));
+                }
+                if permissions.starts_with('d') {
+                    return Ok(Some(ListTypes::Dir { name }));
+                }
+                Ok(Some(ListTypes::File {
+                    size: size
+                        .parse::<u32>()
+                        .map_err(|_| ParseListTypeError::BadSize(size.to_string()))?,
+                    name,
+                }))
+            }
+            [_, _, _, _, _, ..] => Err(ParseListTypeError::MissingName),
+            _ => Err(ParseListTypeError::MissingFields),
+        }
+    }
+}
+
+//Line numbers start at 1. `Commands::from_str` counts them from the start of the command, and
+//`parse_input` from the start of the input.
 #[derive(Debug, PartialEq)]
-struct ParseCommandError;
+enum ParseCommandError {
+    NotACommand {
+        line: usize,
+    },
+    MissingCommand {
+        line: usize,
+    },
+    UnknownCommand {
+        line: usize,
+        command: String,
+    },
+    BadArgument {
+        line: usize,
+        argument: String,
+    },
+    MissingPath {
+        line: usize,
+    },
+    //Lines after a command that does not print anything, or too many of them
+    UnexpectedOutput {
+        line: usize,
+    },
+    BadEntry {
+        line: usize,
+        error: ParseListTypeError,
+    },
+}
+
+impl ParseCommandError {
+    fn shift_lines(self, n: usize) -> Self {
+        match self {
+            ParseCommandError::NotACommand { line } => {
+                ParseCommandError::NotACommand { line: line + n }
+            }
+            ParseCommandError::MissingCommand { line } => {
+                ParseCommandError::MissingCommand { line: line + n }
+            }
+            ParseCommandError::UnknownCommand { line, command } => {
+                ParseCommandError::UnknownCommand {
+                    line: line + n,
+                    command,
+                }
+            }
+            ParseCommandError::BadArgument { line, argument } => ParseCommandError::BadArgument {
+                line: line + n,
+                argument,
+            },
+            ParseCommandError::MissingPath { line } => {
+                ParseCommandError::MissingPath { line: line + n }
+            }
+            ParseCommandError::UnexpectedOutput { line } => {
+                ParseCommandError::UnexpectedOutput { line: line + n }
+            }
+            ParseCommandError::BadEntry { line, error } => ParseCommandError::BadEntry {
+                line: line + n,
+                error,
+            },
+        }
+    }
+}
+
 impl std::str::FromStr for Commands {
     type Err = ParseCommandError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut lines = s.lines().peekable();
-        let first_line = lines.next().ok_or(ParseCommandError)?;
+        Commands::from_lines(&s.lines().collect::<Vec<_>>())
+    }
+}
+
+impl Commands {
+    /// Reads a command line and the lines of its output.
+    fn from_lines(lines: &[&str]) -> Result<Self, ParseCommandError> {
+        let mut lines = lines.iter().copied();
+        let first_line = lines
+            .next()
+            .ok_or(ParseCommandError::MissingCommand { line: 1 })?;
         let mut words = first_line.split_ascii_whitespace();
         if words.next() != Some("$") {
-            return Err(ParseCommandError);
+            return Err(ParseCommandError::NotACommand { line: 1 });
         }
-        match words.next() {
-            Some("cd") => Ok(Commands::ChangeDirectory {
-                path: words.next().map(|path| path.to_string()),
-            }),
-            Some("ls") => Ok(Commands::List {
-                list: lines
-                    .map(|line| ListTypes::from_str(line).unwrap())
-                    .collect(),
-            }),
-            _ => Err(ParseCommandError),
+        let command = words
+            .next()
+            .ok_or(ParseCommandError::MissingCommand { line: 1 })?;
+        let arguments: Vec<&str> = words.collect();
+        let bad_argument = |argument: &str| ParseCommandError::BadArgument {
+            line: 1,
+            argument: argument.to_string(),
+        };
+        //The output lines, numbered from the line after the command
+        let mut output = lines.enumerate().map(|(i, line)| (i + 2, line));
+
+        let command = match (command, arguments.as_slice()) {
+            ("cd", []) => Commands::ChangeDirectory { path: None },
+            ("cd", [path]) => Commands::ChangeDirectory {
+                path: Some(path.to_string()),
+            },
+            ("ls", []) => {
+                let list = output
+                    .map(|(line, text)| {
+                        ListTypes::from_str(text)
+                            .map_err(|error| ParseCommandError::BadEntry { line, error })
+                    })
+                    .collect::<Result<_, _>>()?;
+                return Ok(Commands::List { list });
+            }
+            ("ls", ["-l"]) => {
+                let list = output
+                    .map(|(line, text)| {
+                        ListTypes::from_long_str(text)
+                            .map_err(|error| ParseCommandError::BadEntry { line, error })
+                    })
+                    .filter_map(Result::transpose)
+                    .collect::<Result<_, _>>()?;
+                return Ok(Commands::List { list });
+            }
+            ("pwd", []) => {
+                let path = output.next().map(|(_, path)| path.trim().to_string());
+                Commands::PrintDirectory { path }
+            }
+            ("rm", []) | ("rm", ["-r"]) => return Err(ParseCommandError::MissingPath { line: 1 }),
+            ("rm", [path]) => Commands::Remove {
+                path: path.to_string(),
+                recursive: false,
+            },
+            ("rm", ["-r", path]) => Commands::Remove {
+                path: path.to_string(),
+                recursive: true,
+            },
+            ("cd" | "ls" | "pwd" | "rm", arguments) => {
+                //The first option that is not known, or else the first path too many
+                let known_options: &[&str] = match command {
+                    "ls" => &["-l"],
+                    "rm" => &["-r"],
+                    _ => &[],
+                };
+                let max_paths = if command == "cd" || command == "rm" {
+                    1
+                } else {
+                    0
+                };
+                let argument = arguments
+                    .iter()
+                    .find(|argument| argument.starts_with('-') && !known_options.contains(argument))
+                    .or_else(|| {
+                        arguments
+                            .iter()
+                            .filter(|argument| !argument.starts_with('-'))
+                            .nth(max_paths)
+                    })
+                    .unwrap_or(&arguments[0]);
+                return Err(bad_argument(argument));
+            }
+            (command, _) => {
+                return Err(ParseCommandError::UnknownCommand {
+                    line: 1,
+                    command: command.to_string(),
+                })
+            }
+        };
+
+        match output.next() {
+            Some((line, _)) => Err(ParseCommandError::UnexpectedOutput { line }),
+            None => Ok(command),
         }
     }
 }
@@ -66,103 +300,276 @@ impl std::str::FromStr for Commands {
 fn parse_input(input: &str) -> Result<Vec<Commands>, ParseCommandError> {
     let mut commands = Vec::new();
 
-    let mut lines = input.lines().peekable();
-    let mut curr_command = String::new();
-    while let Some(line) = lines.next() {
-        curr_command.clear();
-        curr_command.push_str(line);
-        loop {
-            let next_line = lines.peek();
-            if next_line.is_none() || next_line.unwrap().starts_with('$') {
-                break;
-            }
-            curr_command.push('\n');
-            let line = lines.next().unwrap();
-            curr_command.push_str(line);
-        }
-        commands.push(Commands::from_str(&curr_command)?)
+    let lines: Vec<&str> = input.lines().collect();
+    let mut first_line = 0;
+    while first_line < lines.len() {
+        let output_len = lines[first_line + 1..]
+            .iter()
+            .take_while(|line| !line.starts_with('$'))
+            .count();
+        let command_lines = &lines[first_line..first_line + 1 + output_len];
+        commands.push(
+            Commands::from_lines(command_lines).map_err(|error| error.shift_lines(first_line))?,
+        );
+        first_line += command_lines.len();
     }
 
     Ok(commands)
 }
 
-#[derive(Debug)]
-struct Node<'a> {
-    name: &'a str,
-    size: u32,
-    childs_idx: HashMap<&'a str, usize>,
-    parent_idx: Option<usize>,
+fn get_answer_a(commands: &[Commands]) -> Result<u64, FsError> {
+    const MAX_DIR_SIZE: u64 = 100000;
+
+    let fs = VirtualFs::from_commands(commands)?;
+    let total_sizes = fs.total_sizes();
+
+    Ok(fs
+        .nodes()
+        .zip(total_sizes)
+        .filter(|((_, node), size)| node.is_dir() && *size <= MAX_DIR_SIZE)
+        .map(|(_, size)| size)
+        .sum())
+}
+
+/// How much has to be deleted for the update to fit, None if the disk is over capacity.
+fn space_to_free(total_sizes: &[u64]) -> Option<u64> {
+    const DISK_SIZE: u64 = 70000000;
+    const NEEDED_SPACE: u64 = 30000000;
+
+    let free_space = DISK_SIZE.checked_sub(total_sizes[VirtualFs::ROOT])?;
+    Some(NEEDED_SPACE.saturating_sub(free_space))
+}
+
+fn get_answer_b(commands: &[Commands]) -> Result<Option<u64>, FsError> {
+    let fs = VirtualFs::from_commands(commands)?;
+    let total_sizes = fs.total_sizes();
+
+    let Some(to_free) = space_to_free(&total_sizes) else {
+        return Ok(None);
+    };
+
+    Ok(fs
+        .nodes()
+        .zip(total_sizes)
+        .filter(|((_, node), size)| node.is_dir() && *size >= to_free)
+        .map(|(_, size)| size)
+        .min())
 }
 
-impl<'a> Node<'a> {
-    fn new(name: &'a str, size: u32) -> Self {
-        let childs_idx = HashMap::new();
-        let parent_idx = None;
+fn main() {
+    let mut lookups = Vec::new();
+    let mut du = false;
+    let mut tree = false;
+    let mut options = ReportOptions::default();
+    let mut reclaim = None;
+    let mut needed = None;
+    let mut generate_from_dir = None;
+    let mut generate_from_json = None;
+    let mut transcript_options = TranscriptOptions::default();
+    let mut materialize_into = None;
+    let mut input_path = String::from("./input/input.txt");
+    let mut run_shell = false;
 
-        Self {
-            name,
-            size,
-            childs_idx,
-            parent_idx,
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input_path = args.next().expect("Expecting a file after --input"),
+            "--shell" => run_shell = true,
+            "--lookup" => lookups.push(args.next().expect("Expecting a path after --lookup")),
+            "--du" => du = true,
+            "--tree" => tree = true,
+            "--max-depth" => {
+                options.max_depth = Some(
+                    args.next()
+                        .and_then(|depth| depth.parse().ok())
+                        .expect("Expecting a depth after --max-depth"),
+                )
+            }
+            "--sort" => {
+                options.sort = match args.next().as_deref() {
+                    Some("name") => SortKey::Name,
+                    Some("size") => SortKey::Size,
+                    _ => panic!("Expecting name or size after --sort"),
+                }
+            }
+            "--min-size" => {
+                options.min_size = args
+                    .next()
+                    .and_then(|size| report::parse_size(&size))
+                    .expect("Expecting a size after --min-size")
+            }
+            "-h" | "--human" => options.human = true,
+            "--reclaim" => {
+                reclaim = match args.next().as_deref() {
+                    Some("bytes") => Some(Objective::Bytes),
+                    Some("count") => Some(Objective::Count),
+                    _ => panic!("Expecting bytes or count after --reclaim"),
+                }
+            }
+            "--needed" => {
+                needed = Some(
+                    args.next()
+                        .and_then(|size| report::parse_size(&size))
+                        .expect("Expecting a size after --needed"),
+                )
+            }
+            "--generate-from-dir" => {
+                generate_from_dir = Some(
+                    args.next()
+                        .expect("Expecting a directory after --generate-from-dir"),
+                )
+            }
+            "--generate-from-json" => {
+                generate_from_json = Some(
+                    args.next()
+                        .expect("Expecting a file after --generate-from-json"),
+                )
+            }
+            "--order" => {
+                transcript_options.order = match args.next().as_deref() {
+                    Some("depth") => Order::DepthFirst,
+                    Some("breadth") => Order::BreadthFirst,
+                    _ => panic!("Expecting depth or breadth after --order"),
+                }
+            }
+            "--materialize" => {
+                materialize_into = Some(
+                    args.next()
+                        .expect("Expecting a directory after --materialize"),
+                )
+            }
+            "--redundant-cd" => transcript_options.redundant_root_cd = true,
+            "--repeat-listings" => {
+                transcript_options.repeated_listings = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("Expecting a number after --repeat-listings")
+            }
+            arg => panic!("Unknown argument: {}", arg),
         }
     }
-}
 
-fn build_file_tree(commands: &Vec<Commands>) -> Vec<Node> {
-    let mut nodes: Vec<Node> = vec![Node::new("/", 0)];
-
-    let mut curr_node_idx = 0;
-    for command in commands {
-        println!("Command: {:?}", command);
-        match command {
-            Commands::ChangeDirectory { path } => match path {
-                Some(str) => match str.as_str() {
-                    "/" => {}
-                    ".." => {
-                        curr_node_idx = nodes[curr_node_idx]
-                            .parent_idx
-                            .expect("Failed to get parent directory")
-                    }
-                    str => {
-                        curr_node_idx = *nodes[curr_node_idx]
-                            .childs_idx
-                            .get(str)
-                            .unwrap_or_else(|| panic!("No directory with name: {}", str));
-                    }
-                },
-                None => panic!("No path provided"),
-            },
-            Commands::List { list } => {
-                for child in list {
-                    let (name, size) = match child {
-                        ListTypes::File { name, size } => (name, *size),
-                        ListTypes::Dir { name } => (name, 0),
-                    };
-                    let new_node_idx = nodes.len();
-                    let mut new_node = Node::new(name, size);
-                    new_node.parent_idx = Some(curr_node_idx);
-                    nodes.push(new_node);
-                    nodes[curr_node_idx].childs_idx.insert(name, new_node_idx);
+    //Generating a transcript does not need the input
+    let source = match (generate_from_dir, generate_from_json) {
+        (Some(dir), _) => Some(transcript::from_dir(Path::new(&dir))),
+        (None, Some(json)) => Some(transcript::from_json(
+            &read_to_string(json).expect("Expecting a readable JSON file"),
+        )),
+        (None, None) => None,
+    };
+    if let Some(source) = source {
+        let fs = source.expect("Expecting a tree the transcript can describe");
+        print!(
+            "{}",
+            transcript::to_text(&transcript::generate(&fs, &transcript_options))
+        );
+        return;
+    }
+
+    let input = read_to_string(input_path).expect("Expecting valid input file");
+    let commands = parse_input(&input).expect("Expecting valid terminal output");
+
+    if run_shell {
+        let mut shell = shell::Shell::new(commands).expect("Expecting a consistent file tree");
+        shell::run(&mut shell).expect("Expecting a working terminal");
+        return;
+    }
+
+    if let Some(target) = materialize_into {
+        let fs = VirtualFs::from_commands(&commands).expect("Expecting a consistent file tree");
+        let target = Path::new(&target);
+        materialize::materialize(&fs, target).expect("Expecting the tree to be created");
+        let mismatches = materialize::verify(&fs, target).expect("Expecting the tree to be read");
+        for mismatch in &mismatches {
+            println!(
+                "{}: expected {:?}, found {:?}",
+                mismatch.path, mismatch.expected, mismatch.found
+            );
+        }
+        println!(
+            "Created {} nodes, {} mismatches",
+            fs.nodes().count(),
+            mismatches.len()
+        );
+        return;
+    }
+
+    if let Some(objective) = reclaim {
+        let fs = VirtualFs::from_commands(&commands).expect("Expecting a consistent file tree");
+        let total_sizes = fs.total_sizes();
+        //By default, free the space needed for the update
+        let needed = needed
+            .or_else(|| space_to_free(&total_sizes))
+            .expect("Expecting the disk to hold the file tree");
+        match reclaim::plan(&fs, needed, objective, reclaim::MAX_STATES) {
+            Some(plan) => {
+                for id in &plan.deleted {
+                    println!(
+                        "{}\t{}",
+                        report::format_size(total_sizes[*id], options.human),
+                        fs.path_of(*id)
+                    );
                 }
+                println!(
+                    "Freed {} in {} deletions{}",
+                    plan.freed,
+                    plan.deleted.len(),
+                    if plan.exact { "" } else { " (approximate)" }
+                );
             }
+            None => println!("Can not free {} bytes", needed),
         }
+        return;
     }
-    nodes
-}
 
-fn get_answer_a(commands: &Vec<Commands>) -> u32 {
-    let nodes = build_file_tree(commands);
+    if du || tree {
+        let fs = VirtualFs::from_commands(&commands).expect("Expecting a consistent file tree");
+        if du {
+            print!("{}", report::du(&fs, VirtualFs::ROOT, &options));
+        }
+        if tree {
+            print!("{}", report::tree(&fs, &options));
+        }
+        return;
+    }
 
-    //0 is root always root
-    let curr_node_idx = 0;
+    if !lookups.is_empty() {
+        let fs = VirtualFs::from_commands(&commands).expect("Expecting a consistent file tree");
+        let total_sizes = fs.total_sizes();
+        //Paths are looked up from the root, not from where the transcript ended
+        for path in lookups {
+            match fs.lookup(&format!("/{}", path)) {
+                Ok(id) => println!(
+                    "{} ({}): {}",
+                    fs.path_of(id),
+                    if fs.node(id).is_dir() { "dir" } else { "file" },
+                    total_sizes[id]
+                ),
+                Err(error) => println!("{}: {:?}", path, error),
+            }
+        }
+        return;
+    }
 
-    todo!()
+    println!(
+        "Part 1: {}",
+        get_answer_a(&commands).expect("Expecting a consistent file tree")
+    );
+    println!(
+        "Part 2: {}",
+        get_answer_b(&commands)
+            .expect("Expecting a consistent file tree")
+            .unwrap()
+    );
 }
 
-fn main() {
-    let input_path = Path::new("./input/input.txt");
-    let input = read_to_string(input_path).expect("Expecting valid input file");
-    todo!()
+#[cfg(test)]
+const TEST_INPUT: &str = include_str!("./test_input.txt");
+
+/// The tree left by a transcript, for the tests of every module.
+#[cfg(test)]
+fn replay(text: &str) -> Result<VirtualFs, FsError> {
+    VirtualFs::from_commands(&parse_input(text).unwrap())
 }
 
 #[cfg(test)]
@@ -171,7 +578,12 @@ mod tests {
 
     use super::*;
 
-    const TEST_INPUT: &str = include_str!("./test_input.txt");
+    #[test]
+    fn test_get_answers() {
+        let commands = parse_input(TEST_INPUT).unwrap();
+        assert_eq!(get_answer_a(&commands), Ok(95437));
+        assert_eq!(get_answer_b(&commands), Ok(Some(24933642)));
+    }
 
     #[test]
     fn test_parse_input() {
@@ -258,10 +670,135 @@ mod tests {
                 ],
             },
         ];
-        assert_eq!(get_answer_a(&expected_commands), 95437);
+        assert_eq!(get_answer_a(&expected_commands), Ok(95437));
+        assert_eq!(get_answer_b(&expected_commands), Ok(Some(24933642)));
         assert_eq!(
             parse_input(&String::from_str(TEST_INPUT).unwrap()),
             Ok(expected_commands)
         );
     }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            (
+                "$ cd /\n$ ls\ndir a\n12x b\n",
+                ParseCommandError::BadEntry {
+                    line: 4,
+                    error: ParseListTypeError::BadSize("12x".to_string()),
+                },
+            ),
+            (
+                "$ cd /\n$ ls\n\n$ cd a",
+                ParseCommandError::BadEntry {
+                    line: 3,
+                    error: ParseListTypeError::Empty,
+                },
+            ),
+            (
+                "$ ls\n10 a\ndir",
+                ParseCommandError::BadEntry {
+                    line: 3,
+                    error: ParseListTypeError::MissingName,
+                },
+            ),
+            (
+                "$ cd /\n$ mv a b",
+                ParseCommandError::UnknownCommand {
+                    line: 2,
+                    command: "mv".to_string(),
+                },
+            ),
+            ("10 a", ParseCommandError::NotACommand { line: 1 }),
+            ("$ cd /\n$", ParseCommandError::MissingCommand { line: 2 }),
+            (
+                "$ ls -a",
+                ParseCommandError::BadArgument {
+                    line: 1,
+                    argument: "-a".to_string(),
+                },
+            ),
+            (
+                "$ cd a b",
+                ParseCommandError::BadArgument {
+                    line: 1,
+                    argument: "b".to_string(),
+                },
+            ),
+            ("$ ls\n$ rm -r", ParseCommandError::MissingPath { line: 2 }),
+            (
+                "$ cd /\n10 a",
+                ParseCommandError::UnexpectedOutput { line: 2 },
+            ),
+            (
+                "$ pwd\n/\n/",
+                ParseCommandError::UnexpectedOutput { line: 3 },
+            ),
+        ];
+        for (input, error) in cases {
+            assert_eq!(parse_input(input), Err(error), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_extra_commands() {
+        let input = "$ cd /\n$ ls -l\ntotal 8\ndrwxr-xr-x 2 user group 4096 Dec 7 10:00 a\n\
+                     -rw-r--r-- 1 user group 14848514 Dec 7 10:00 b.txt\n\
+                     -rw-r--r-- 1 user group 10 Dec 7 10:00 my notes.txt\n$ cd a\n$ pwd\n/a";
+        let commands = parse_input(input).unwrap();
+        assert_eq!(
+            commands[1],
+            Commands::List {
+                list: vec![
+                    ListTypes::Dir {
+                        name: "a".to_string()
+                    },
+                    ListTypes::File {
+                        name: "b.txt".to_string(),
+                        size: 14848514
+                    },
+                    ListTypes::File {
+                        name: "my notes.txt".to_string(),
+                        size: 10
+                    },
+                ]
+            }
+        );
+        //Names with spaces are written back in a way that reads the same
+        assert_eq!(
+            Commands::from_str(&commands[1].to_string()).as_ref(),
+            Ok(&commands[1])
+        );
+        assert_eq!(
+            commands[3],
+            Commands::PrintDirectory {
+                path: Some("/a".to_string())
+            }
+        );
+        assert!(VirtualFs::from_commands(&commands).is_ok());
+        assert_eq!(
+            parse_input("$ ls -l\nlrwxrwxrwx 1 user group 5 Dec 7 10:00 link -> a b"),
+            Err(ParseCommandError::BadEntry {
+                line: 2,
+                error: ParseListTypeError::Symlink("link -> a b".to_string())
+            })
+        );
+        assert_eq!(
+            parse_input("$ ls -l\n-rw-r--r-- 1 user group 10 Dec 7"),
+            Err(ParseCommandError::BadEntry {
+                line: 2,
+                error: ParseListTypeError::MissingName
+            })
+        );
+
+        //The printed path has to be where the transcript is
+        let commands = parse_input("$ cd /a\n$ pwd\n/b").unwrap();
+        assert_eq!(
+            VirtualFs::from_commands(&commands),
+            Err(FsError::Conflict {
+                path: "/b".to_string(),
+                reason: "Printed while in /a".to_string()
+            })
+        );
+    }
 }
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+use crate::transcript::{self, SourceError};
+use crate::vfs::{NodeKind, VirtualFs};
+
+#[derive(Debug, PartialEq)]
+pub enum MaterializeError {
+    Io(String),
+    //A name that is not a single path component, like `..` or `a/b`
+    UnsafeName(String),
+    //A path that already exists but would lead outside the target, like a symbolic link
+    Escape(String),
+    //The target could not be read back
+    Verify(SourceError),
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> MaterializeError {
+    MaterializeError::Io(format!("{}: {}", path.display(), error))
+}
+
+/// Whether a name stays a single component of a path under the target.
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\0'])
+}
+
+/// Creates the directories and files of the tree under `target`, which becomes the root. Files are
+/// only given their length, so they are sparse and nothing is written in them.
+///
+/// Existing directories are reused, but existing files are never overwritten, and nothing is
+/// created through a symbolic link.
+pub fn materialize(fs: &VirtualFs, target: &Path) -> Result<(), MaterializeError> {
+    std::fs::create_dir_all(target).map_err(|error| io_error(target, error))?;
+    let root = target
+        .canonicalize()
+        .map_err(|error| io_error(target, error))?;
+
+    //Parents are always before their children in the arena
+    let mut paths: Vec<PathBuf> = vec![root];
+    for (id, node) in fs.nodes().skip(1) {
+        if !is_safe_name(&node.name) {
+            return Err(MaterializeError::UnsafeName(fs.path_of(id)));
+        }
+        let parent = node.parent.expect("Only the root has no parent");
+        let path = paths[parent].join(&node.name);
+
+        match (std::fs::symlink_metadata(&path), &node.kind) {
+            (Ok(metadata), _) if metadata.is_symlink() => {
+                return Err(MaterializeError::Escape(path.display().to_string()));
+            }
+            (Ok(metadata), NodeKind::Dir { .. }) if metadata.is_dir() => {}
+            (Ok(_), _) => {
+                return Err(MaterializeError::Io(format!(
+                    "{}: Already exists",
+                    path.display()
+                )));
+            }
+            (Err(_), NodeKind::Dir { .. }) => {
+                std::fs::create_dir(&path).map_err(|error| io_error(&path, error))?
+            }
+            (Err(_), NodeKind::File { size }) => OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .and_then(|file| file.set_len(*size as u64))
+                .map_err(|error| io_error(&path, error))?,
+        }
+        paths.push(path);
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Entry {
+    Dir,
+    File(u32),
+}
+
+/// A path whose entry differs between the tree and the target. None when it is missing.
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub path: String,
+    pub expected: Option<Entry>,
+    pub found: Option<Entry>,
+}
+
+fn entries(fs: &VirtualFs) -> BTreeMap<String, Entry> {
+    fs.nodes()
+        .map(|(id, node)| {
+            let entry = match node.kind {
+                NodeKind::File { size } => Entry::File(size),
+                NodeKind::Dir { .. } => Entry::Dir,
+            };
+            (fs.path_of(id), entry)
+        })
+        .collect()
+}
+
+/// Reads the target back and lists every difference with the tree, in path order.
+pub fn verify(fs: &VirtualFs, target: &Path) -> Result<Vec<Mismatch>, MaterializeError> {
+    let expected = entries(fs);
+    let found = entries(&transcript::from_dir(target).map_err(MaterializeError::Verify)?);
+
+    let mut paths: Vec<&String> = expected.keys().chain(found.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    Ok(paths
+        .into_iter()
+        .filter_map(|path| {
+            let expected = expected.get(path).copied();
+            let found = found.get(path).copied();
+            (expected != found).then(|| Mismatch {
+                path: path.clone(),
+                expected,
+                found,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{replay, TEST_INPUT};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("day07-materialize-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_materialize() {
+        let target = temp_dir("tree");
+        let fs = replay(TEST_INPUT).unwrap();
+        let result = materialize(&fs, &target);
+        let mismatches = verify(&fs, &target);
+        let size = std::fs::metadata(target.join("d/d.log")).map(|metadata| metadata.len());
+        std::fs::write(target.join("a/e/i"), b"changed").unwrap();
+        std::fs::create_dir(target.join("z")).unwrap();
+        let mismatches_after_change = verify(&fs, &target);
+        //Existing files are not overwritten
+        let second_result = materialize(&fs, &target);
+        std::fs::remove_dir_all(&target).unwrap();
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(mismatches, Ok(vec![]));
+        assert_eq!(size.unwrap(), 8033020);
+        assert_eq!(
+            mismatches_after_change,
+            Ok(vec![
+                Mismatch {
+                    path: "/a/e/i".to_string(),
+                    expected: Some(Entry::File(584)),
+                    found: Some(Entry::File(7)),
+                },
+                Mismatch {
+                    path: "/z".to_string(),
+                    expected: None,
+                    found: Some(Entry::Dir),
+                },
+            ])
+        );
+        assert!(matches!(second_result, Err(MaterializeError::Io(_))));
+    }
+
+    #[test]
+    fn test_materialize_unsafe_names() {
+        let target = temp_dir("unsafe");
+        //Listings refuse such names, but trees can also be built directly
+        for name in ["", ".", "..", "a/../../b", "a\0b"] {
+            let mut fs = VirtualFs::new();
+            fs.add_file(VirtualFs::ROOT, name, 10);
+            assert!(matches!(
+                materialize(&fs, &target),
+                Err(MaterializeError::UnsafeName(_))
+            ));
+        }
+
+        //Spaces stay in a single component
+        let mut fs = VirtualFs::new();
+        fs.add_file(VirtualFs::ROOT, "my notes.txt", 10);
+        let result = materialize(&fs, &target);
+        let size = std::fs::metadata(target.join("my notes.txt")).map(|metadata| metadata.len());
+        std::fs::remove_dir_all(&target).unwrap();
+        assert_eq!(result, Ok(()));
+        assert_eq!(size.unwrap(), 10);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_materialize_through_symlink() {
+        let target = temp_dir("symlink");
+        let outside = temp_dir("outside");
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, target.join("a")).unwrap();
+        let result = materialize(&replay(TEST_INPUT).unwrap(), &target);
+        let outside_entries = std::fs::read_dir(&outside).unwrap().count();
+        std::fs::remove_dir_all(&target).unwrap();
+        std::fs::remove_dir_all(&outside).unwrap();
+
+        assert!(matches!(result, Err(MaterializeError::Escape(_))));
+        assert_eq!(outside_entries, 0);
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+
+use crate::vfs::{NodeId, VirtualFs};
+
+/// Past this many partial sums per directory, the search for the fewest bytes is approximated.
+pub const MAX_STATES: usize = 1 << 10;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Objective {
+    //Delete as few bytes as possible
+    Bytes,
+    //Delete as few directories and files as possible, then as few bytes as possible
+    Count,
+}
+
+/// Directories and files to delete, none of them inside another one.
+#[derive(Debug, PartialEq)]
+pub struct Plan {
+    pub deleted: Vec<NodeId>,
+    pub freed: u64,
+    //Whether the plan is known to be the best one for its objective
+    pub exact: bool,
+}
+
+type LinkId = usize;
+
+//How a set of deletions was built, as a union of two smaller sets. Links only point to older links.
+#[derive(Debug, Clone, Copy)]
+enum Link {
+    Empty,
+    Delete(NodeId),
+    Join(LinkId, LinkId),
+}
+
+const EMPTY: LinkId = 0;
+
+/// The sums below the needed space that some deletions free, in increasing order, and the
+/// smallest sum reaching it.
+#[derive(Debug, Clone)]
+struct Level {
+    below: Vec<(u64, LinkId)>,
+    enough: Option<(u64, LinkId)>,
+}
+
+impl Level {
+    fn empty() -> Self {
+        Level {
+            below: Vec::new(),
+            enough: None,
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &(u64, LinkId)> {
+        self.below.iter().chain(&self.enough)
+    }
+
+    /// Adds another way to free bytes, kept only if no other way frees exactly as many.
+    fn add(&mut self, sum: u64, link: LinkId, needed: u64) {
+        if sum >= needed {
+            if self.enough.is_none_or(|(enough, _)| sum < enough) {
+                self.enough = Some((sum, link));
+            }
+        } else if let Err(i) = self.below.binary_search_by_key(&sum, |&(sum, _)| sum) {
+            self.below.insert(i, (sum, link));
+        }
+    }
+}
+
+/// The sums of a merge, each with the two links it comes from. Past `max_states` sums, only the
+/// biggest one of each bucket of sums is kept.
+struct Collector {
+    needed: u64,
+    max_states: usize,
+    sums: HashMap<u64, (LinkId, LinkId)>,
+    width: u64,
+    buckets: Vec<Option<(u64, (LinkId, LinkId))>>,
+    enough: Option<(u64, (LinkId, LinkId))>,
+}
+
+impl Collector {
+    fn new(needed: u64, max_states: usize) -> Self {
+        Collector {
+            needed,
+            max_states,
+            sums: HashMap::new(),
+            width: 1,
+            buckets: Vec::new(),
+            enough: None,
+        }
+    }
+
+    fn insert(&mut self, sum: u64, links: (LinkId, LinkId)) {
+        if sum >= self.needed {
+            if self.enough.is_none_or(|(enough, _)| sum < enough) {
+                self.enough = Some((sum, links));
+            }
+        } else if self.buckets.is_empty() {
+            self.sums.entry(sum).or_insert(links);
+            if self.sums.len() > self.max_states {
+                self.use_buckets();
+            }
+        } else {
+            let bucket = &mut self.buckets[(sum / self.width) as usize];
+            if bucket.is_none_or(|(kept, _)| sum > kept) {
+                *bucket = Some((sum, links));
+            }
+        }
+    }
+
+    /// Anything that reaches the needed space from a dropped sum still does from the kept one,
+    /// deleting less than one bucket width more.
+    fn use_buckets(&mut self) {
+        self.width = self
+            .needed
+            .div_ceil((self.max_states as u64 / 2).max(1))
+            .max(1);
+        self.buckets = vec![None; ((self.needed - 1) / self.width + 1) as usize];
+        for (sum, links) in std::mem::take(&mut self.sums) {
+            self.insert(sum, links);
+        }
+    }
+}
+
+struct BytesSearch<'a> {
+    fs: &'a VirtualFs,
+    total_sizes: &'a [u64],
+    needed: u64,
+    max_states: usize,
+    //Deletions are counted up to this many, otherwise all counts share a single level
+    max_count: Option<usize>,
+    links: Vec<Link>,
+    //The levels of the directories being searched, from the root down
+    stack: Vec<Vec<Level>>,
+    //Unreachable links are dropped once there are this many
+    compact_at: usize,
+    exact: bool,
+}
+
+impl BytesSearch<'_> {
+    fn new<'a>(
+        fs: &'a VirtualFs,
+        total_sizes: &'a [u64],
+        needed: u64,
+        max_states: usize,
+        max_count: Option<usize>,
+    ) -> BytesSearch<'a> {
+        BytesSearch {
+            fs,
+            total_sizes,
+            needed,
+            max_states,
+            max_count,
+            links: vec![Link::Empty],
+            stack: Vec::new(),
+            compact_at: 1 << 20,
+            exact: true,
+        }
+    }
+
+    fn level_of(&self, count: usize) -> Option<usize> {
+        match self.max_count {
+            None => Some(0),
+            Some(max_count) => (count <= max_count).then_some(count),
+        }
+    }
+
+    fn join(&mut self, (a, b): (LinkId, LinkId)) -> LinkId {
+        if b == EMPTY {
+            return a;
+        }
+        if a == EMPTY {
+            return b;
+        }
+        self.links.push(Link::Join(a, b));
+        self.links.len() - 1
+    }
+
+    /// Every way to combine deletions from both sides. Sums of a level are increasing, so each
+    /// row stops at the first sum reaching the needed space.
+    fn merge(&mut self, levels: &[Level], child_levels: &[Level]) -> Vec<Level> {
+        let n_levels = match self.max_count {
+            None => 1,
+            Some(max_count) => (levels.len() + child_levels.len() - 1).min(max_count + 1),
+        };
+        let mut collectors: Vec<Collector> = (0..n_levels)
+            .map(|_| Collector::new(self.needed, self.max_states))
+            .collect();
+        for (count, level) in levels.iter().enumerate() {
+            for (child_count, child_level) in child_levels.iter().enumerate() {
+                let Some(i) = self.level_of(count + child_count) else {
+                    continue;
+                };
+                for &(sum, link) in level.entries() {
+                    for &(child_sum, child_link) in child_level.entries() {
+                        collectors[i].insert(sum + child_sum, (link, child_link));
+                        if sum + child_sum >= self.needed {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut merged = Vec::with_capacity(n_levels);
+        for collector in collectors {
+            self.exact &= collector.buckets.is_empty();
+            let mut below: Vec<(u64, (LinkId, LinkId))> = if collector.buckets.is_empty() {
+                collector.sums.into_iter().collect()
+            } else {
+                collector.buckets.into_iter().flatten().collect()
+            };
+            below.sort_unstable_by_key(|&(sum, _)| sum);
+            merged.push(Level {
+                below: below
+                    .into_iter()
+                    .map(|(sum, links)| (sum, self.join(links)))
+                    .collect(),
+                enough: collector.enough.map(|(sum, links)| (sum, self.join(links))),
+            });
+        }
+        merged
+    }
+
+    /// Drops the links that the levels being searched do not lead to.
+    fn compact(&mut self) {
+        let mut is_live = vec![false; self.links.len()];
+        let mut to_visit: Vec<LinkId> = self
+            .stack
+            .iter()
+            .flatten()
+            .flat_map(|level| level.entries().map(|&(_, link)| link))
+            .collect();
+        to_visit.push(EMPTY);
+        while let Some(link) = to_visit.pop() {
+            if !is_live[link] {
+                is_live[link] = true;
+                if let Link::Join(a, b) = self.links[link] {
+                    to_visit.extend([a, b]);
+                }
+            }
+        }
+
+        let mut new_ids = vec![EMPTY; self.links.len()];
+        let mut kept = Vec::new();
+        for (link, &live) in is_live.iter().enumerate() {
+            if live {
+                new_ids[link] = kept.len();
+                kept.push(match self.links[link] {
+                    Link::Join(a, b) => Link::Join(new_ids[a], new_ids[b]),
+                    other => other,
+                });
+            }
+        }
+        for level in self.stack.iter_mut().flatten() {
+            for (_, link) in level.below.iter_mut().chain(&mut level.enough) {
+                *link = new_ids[*link];
+            }
+        }
+        self.links = kept;
+        self.compact_at = self.compact_at.max(self.links.len() * 2);
+    }
+
+    fn search(&mut self, id: NodeId) -> Vec<Level> {
+        let mut start = Level::empty();
+        start.add(0, EMPTY, self.needed);
+        self.stack.push(vec![start]);
+        for &child in self.fs.children(id).into_iter().flat_map(|c| c.values()) {
+            let child_levels = self.search(child);
+            let levels = self.stack.pop().unwrap();
+            let merged = self.merge(&levels, &child_levels);
+            self.stack.push(merged);
+            if self.links.len() >= self.compact_at {
+                self.compact();
+            }
+        }
+        let mut levels = self.stack.pop().unwrap();
+
+        //The root itself can not be deleted
+        let size = self.total_sizes[id];
+        if let (true, Some(i)) = (id != VirtualFs::ROOT && size > 0, self.level_of(1)) {
+            if levels.len() <= i {
+                levels.resize(i + 1, Level::empty());
+            }
+            self.links.push(Link::Delete(id));
+            levels[i].add(size, self.links.len() - 1, self.needed);
+        }
+        levels
+    }
+
+    fn deleted(&self, link: LinkId) -> Vec<NodeId> {
+        let mut deleted = Vec::new();
+        let mut to_visit = vec![link];
+        while let Some(link) = to_visit.pop() {
+            match self.links[link] {
+                Link::Empty => {}
+                Link::Delete(id) => deleted.push(id),
+                Link::Join(a, b) => to_visit.extend([a, b]),
+            }
+        }
+        deleted.sort_unstable();
+        deleted
+    }
+}
+
+/// For each number of deletions, the most bytes they can free.
+fn search_count(fs: &VirtualFs, total_sizes: &[u64], needed: u64, id: NodeId) -> Vec<Option<u64>> {
+    let mut counts = vec![Some(0)];
+    for &child in fs.children(id).into_iter().flat_map(|c| c.values()) {
+        let child_counts = search_count(fs, total_sizes, needed, child);
+        let mut merged = vec![None; counts.len() + child_counts.len() - 1];
+        for (count, freed) in counts.iter().enumerate() {
+            for (child_count, child_freed) in child_counts.iter().enumerate() {
+                if let (Some(freed), Some(child_freed)) = (freed, child_freed) {
+                    let slot = &mut merged[count + child_count];
+                    *slot = (*slot).max(Some(freed + child_freed));
+                }
+            }
+        }
+        counts = merged;
+        //More deletions than needed to reach the space never help
+        if let Some(enough) = counts
+            .iter()
+            .position(|freed| freed.is_some_and(|freed| freed >= needed))
+        {
+            counts.truncate(enough + 1);
+        }
+    }
+
+    if id != VirtualFs::ROOT {
+        if counts.len() < 2 {
+            counts.push(None);
+        }
+        counts[1] = counts[1].max(Some(total_sizes[id]));
+    }
+    counts
+}
+
+/// Chooses what to delete to free at least `needed` bytes, or None when even deleting everything
+/// is not enough.
+///
+/// Fewest bytes are a subset sum problem: every partial sum is kept while there are at most
+/// `max_states` of them for a directory, after which close sums are merged. The plan is then no
+/// longer exact, and deletes at most `2 * needed / max_states` more bytes than the best one for
+/// each node of the tree. Fewest deletions are always found exactly, and among the plans with that
+/// many the one freeing the fewest bytes is found the same way.
+pub fn plan(fs: &VirtualFs, needed: u64, objective: Objective, max_states: usize) -> Option<Plan> {
+    let total_sizes = fs.total_sizes();
+    let max_count = match objective {
+        Objective::Bytes => None,
+        Objective::Count => Some(
+            search_count(fs, &total_sizes, needed, VirtualFs::ROOT)
+                .iter()
+                .position(|freed| freed.is_some_and(|freed| freed >= needed))?,
+        ),
+    };
+    let mut search = BytesSearch::new(fs, &total_sizes, needed, max_states, max_count);
+    let levels = search.search(VirtualFs::ROOT);
+    //With a count, no fewer deletions are enough
+    let (freed, link) = levels.last()?.enough?;
+    Some(Plan {
+        deleted: search.deleted(link),
+        freed,
+        exact: search.exact,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{replay, TEST_INPUT};
+
+    /// Every set of nodes, none inside another, as the bytes and count they delete.
+    fn brute_force(fs: &VirtualFs) -> Vec<(u64, usize)> {
+        let total_sizes = fs.total_sizes();
+        let ids: Vec<NodeId> = fs.nodes().map(|(id, _)| id).skip(1).collect();
+        let is_inside = |mut id: NodeId, ancestor: NodeId| {
+            while let Some(parent) = fs.node(id).parent {
+                if parent == ancestor {
+                    return true;
+                }
+                id = parent;
+            }
+            false
+        };
+        (0..1_u32 << ids.len())
+            .map(|mask| {
+                (0..ids.len())
+                    .filter(|i| mask & (1 << i) != 0)
+                    .map(|i| ids[i])
+                    .collect::<Vec<_>>()
+            })
+            .filter(|set| set.iter().all(|&a| set.iter().all(|&b| !is_inside(a, b))))
+            .map(|set| {
+                let freed = set.iter().map(|&id| total_sizes[id]).sum();
+                (freed, set.len())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_matches_brute_force() {
+        let fs = replay(TEST_INPUT).unwrap();
+        let candidates = brute_force(&fs);
+        for needed in [0, 584, 30000, 100000, 8381165, 20000000, 40000000, 48381165] {
+            let best_bytes = candidates
+                .iter()
+                .filter(|(freed, _)| *freed >= needed)
+                .map(|(freed, _)| *freed)
+                .min();
+            let bytes_plan = plan(&fs, needed, Objective::Bytes, MAX_STATES);
+            assert_eq!(bytes_plan.as_ref().map(|plan| plan.freed), best_bytes);
+            assert!(bytes_plan.is_none_or(|plan| plan.exact));
+
+            let best_count = candidates
+                .iter()
+                .filter(|(freed, _)| *freed >= needed)
+                .map(|(_, count)| *count)
+                .min();
+            //Among the fewest deletions, the fewest bytes
+            let best_count_bytes = candidates
+                .iter()
+                .filter(|(freed, count)| *freed >= needed && Some(*count) == best_count)
+                .map(|(freed, _)| *freed)
+                .min();
+            let count_plan = plan(&fs, needed, Objective::Count, MAX_STATES);
+            assert_eq!(
+                count_plan.as_ref().map(|plan| plan.deleted.len()),
+                best_count
+            );
+            assert_eq!(count_plan.map(|plan| plan.freed), best_count_bytes);
+        }
+        assert_eq!(plan(&fs, 48381166, Objective::Bytes, MAX_STATES), None);
+    }
+
+    #[test]
+    fn test_plan_example() {
+        //Part two deletes d, but c.dat alone is enough
+        let fs = replay(TEST_INPUT).unwrap();
+        let bytes_plan = plan(&fs, 8381165, Objective::Bytes, MAX_STATES).unwrap();
+        assert_eq!(bytes_plan.freed, 8504156);
+        assert_eq!(
+            bytes_plan
+                .deleted
+                .iter()
+                .map(|&id| fs.path_of(id))
+                .collect::<Vec<_>>(),
+            vec!["/c.dat"]
+        );
+    }
+
+    #[test]
+    fn test_plan_approximation() {
+        //Many files of different sizes, so that the partial sums have to be trimmed
+        let mut transcript = String::from("$ cd /\n$ ls\n");
+        for i in 0..24_u64 {
+            transcript += &format!("{} f{}\n", 1000 + i * i * 37 % 9973, i);
+        }
+        let fs = replay(&transcript).unwrap();
+        let needed = 60000;
+        let exact = plan(&fs, needed, Objective::Bytes, 1 << 20).unwrap();
+        assert!(exact.exact);
+        let approximation = plan(&fs, needed, Objective::Bytes, 64).unwrap();
+        assert!(!approximation.exact);
+        assert!(approximation.freed >= exact.freed);
+        assert!(approximation.freed <= exact.freed + 24 * 2 * needed / 64);
+    }
+
+    #[test]
+    fn test_plan_large_tree() {
+        //Hundreds of directories with files of many sizes, as in a real disk
+        let mut transcript = String::from("$ cd /\n$ ls\n");
+        let mut size = 1_u64;
+        for a in 0..20 {
+            transcript += &format!("dir a{}\n", a);
+        }
+        for a in 0..20 {
+            transcript += &format!("$ cd /a{}\n$ ls\n", a);
+            for b in 0..20 {
+                transcript += &format!("dir b{}\n", b);
+            }
+            for b in 0..20 {
+                transcript += &format!("$ cd /a{}/b{}\n$ ls\n", a, b);
+                for f in 0..4 {
+                    size = size * 48271 % 1000003;
+                    transcript += &format!("{} f{}\n", size * 7, f);
+                }
+            }
+        }
+        let fs = replay(&transcript).unwrap();
+        let total_sizes = fs.total_sizes();
+        let needed = total_sizes[VirtualFs::ROOT] / 3;
+
+        let bytes_plan = plan(&fs, needed, Objective::Bytes, MAX_STATES).unwrap();
+        assert!(bytes_plan.freed >= needed);
+        let nodes = fs.nodes().count() as u64;
+        assert!(bytes_plan.freed <= needed + nodes * 2 * needed / MAX_STATES as u64);
+        let freed: u64 = bytes_plan.deleted.iter().map(|&id| total_sizes[id]).sum();
+        assert_eq!(freed, bytes_plan.freed);
+
+        //A few whole top directories are enough, and the count plan picks the smallest of them
+        let count_plan = plan(&fs, needed, Objective::Count, MAX_STATES).unwrap();
+        let freed: u64 = count_plan.deleted.iter().map(|&id| total_sizes[id]).sum();
+        assert_eq!(freed, count_plan.freed);
+        assert!(count_plan.freed >= needed);
+        assert!(count_plan.deleted.len() <= 7);
+    }
+}
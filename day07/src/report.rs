@@ -0,0 +1,244 @@
+use std::fmt::Write;
+
+use crate::vfs::{NodeId, VirtualFs};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SortKey {
+    Name,
+    //Biggest first, like `du | sort -hr`
+    Size,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportOptions {
+    //Depth below the root, which is at depth 0. No limit when None.
+    pub max_depth: Option<usize>,
+    pub sort: SortKey,
+    //Nodes smaller than this are left out, but still count in the size of their parents
+    pub min_size: u64,
+    pub human: bool,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            max_depth: None,
+            sort: SortKey::Name,
+            min_size: 0,
+            human: false,
+        }
+    }
+}
+
+const UNITS: [char; 4] = ['K', 'M', 'G', 'T'];
+
+/// Formats a size like `du -h`: powers of 1024, rounded up, with one decimal below 10.
+pub fn format_size(size: u64, human: bool) -> String {
+    if !human || size < 1024 {
+        return size.to_string();
+    }
+    let mut value = size as f64;
+    let mut unit = 0;
+    value /= 1024.0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 {
+        let rounded = (value * 10.0).ceil() / 10.0;
+        //Rounding up can reach 10, which is then written without a decimal
+        if rounded < 10.0 {
+            return format!("{:.1}{}", rounded, UNITS[unit]);
+        }
+    }
+    format!("{}{}", value.ceil(), UNITS[unit])
+}
+
+/// Parses a size in bytes, optionally followed by a unit as written by `format_size`.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (number, multiplier) = match UNITS
+        .iter()
+        .position(|unit| s.ends_with(*unit) || s.ends_with(unit.to_ascii_lowercase()))
+    {
+        Some(unit) => (&s[..s.len() - 1], 1024_f64.powi(unit as i32 + 1)),
+        None => (s, 1.0),
+    };
+    let size = number.parse::<f64>().ok()? * multiplier;
+    (size >= 0.0 && size <= u64::MAX as f64).then_some(size.ceil() as u64)
+}
+
+struct Report<'a> {
+    fs: &'a VirtualFs,
+    total_sizes: Vec<u64>,
+    options: &'a ReportOptions,
+}
+
+impl Report<'_> {
+    fn sorted_children(&self, id: NodeId) -> Vec<NodeId> {
+        let mut children: Vec<NodeId> = self
+            .fs
+            .children(id)
+            .map(|children| children.values().copied().collect())
+            .unwrap_or_default();
+        //Children come sorted by name already
+        if self.options.sort == SortKey::Size {
+            children.sort_by_key(|&child| std::cmp::Reverse(self.total_sizes[child]));
+        }
+        children.retain(|&child| self.total_sizes[child] >= self.options.min_size);
+        children
+    }
+
+    fn size(&self, id: NodeId) -> String {
+        format_size(self.total_sizes[id], self.options.human)
+    }
+
+    fn du(&self, id: NodeId, depth: usize, output: &mut String) {
+        //Deeper directories still count in the total of their parents, they are just not shown
+        if self
+            .options
+            .max_depth
+            .is_none_or(|max_depth| depth < max_depth)
+        {
+            for child in self.sorted_children(id) {
+                if self.fs.node(child).is_dir() {
+                    self.du(child, depth + 1, output);
+                }
+            }
+        }
+        writeln!(output, "{}\t{}", self.size(id), self.fs.path_of(id)).unwrap();
+    }
+
+    fn tree(&self, id: NodeId, prefix: &str, depth: usize, output: &mut String) {
+        if self
+            .options
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            return;
+        }
+        let children = self.sorted_children(id);
+        for (i, &child) in children.iter().enumerate() {
+            let is_last = i + 1 == children.len();
+            let node = self.fs.node(child);
+            writeln!(
+                output,
+                "{}{}{} ({})",
+                prefix,
+                if is_last { "└── " } else { "├── " },
+                node.name,
+                self.size(child)
+            )
+            .unwrap();
+            if node.is_dir() {
+                let prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+                self.tree(child, &prefix, depth + 1, output);
+            }
+        }
+    }
+}
+
+/// Lists the directories under `from` with their total size, deepest first, like
+/// `du --max-depth=N`. The depth is counted from `from`.
+pub fn du(fs: &VirtualFs, from: NodeId, options: &ReportOptions) -> String {
+    let report = Report {
+        fs,
+        total_sizes: fs.total_sizes(),
+        options,
+    };
+    let mut output = String::new();
+    report.du(from, 0, &mut output);
+    output
+}
+
+/// Draws the directories and files like `tree`, each with its total size.
+pub fn tree(fs: &VirtualFs, options: &ReportOptions) -> String {
+    let report = Report {
+        fs,
+        total_sizes: fs.total_sizes(),
+        options,
+    };
+    let mut output = format!("/ ({})\n", report.size(VirtualFs::ROOT));
+    report.tree(VirtualFs::ROOT, "", 0, &mut output);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{replay, TEST_INPUT};
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(format_size(584, true), "584");
+        assert_eq!(format_size(94853, true), "93K");
+        assert_eq!(format_size(1536, true), "1.5K");
+        assert_eq!(format_size(10239, true), "10K");
+        assert_eq!(format_size(48381165, true), "47M");
+        assert_eq!(format_size(48381165, false), "48381165");
+        assert_eq!(parse_size("1.5K"), Some(1536));
+        assert_eq!(parse_size("10m"), Some(10 * 1024 * 1024));
+        assert_eq!(parse_size("584"), Some(584));
+        assert_eq!(parse_size("-1"), None);
+        assert_eq!(parse_size("5X"), None);
+    }
+
+    #[test]
+    fn test_du() {
+        let fs = replay(TEST_INPUT).unwrap();
+        assert_eq!(
+            du(&fs, VirtualFs::ROOT, &ReportOptions::default()),
+            "584\t/a/e\n94853\t/a\n24933642\t/d\n48381165\t/\n"
+        );
+        let options = ReportOptions {
+            max_depth: Some(1),
+            sort: SortKey::Size,
+            human: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            du(&fs, VirtualFs::ROOT, &options),
+            "24M\t/d\n93K\t/a\n47M\t/\n"
+        );
+        let options = ReportOptions {
+            min_size: 1000,
+            ..Default::default()
+        };
+        assert_eq!(
+            du(&fs, VirtualFs::ROOT, &options),
+            "94853\t/a\n24933642\t/d\n48381165\t/\n"
+        );
+    }
+
+    #[test]
+    fn test_tree() {
+        let fs = replay(TEST_INPUT).unwrap();
+        let options = ReportOptions {
+            sort: SortKey::Size,
+            min_size: 60000,
+            human: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            tree(&fs, &options),
+            "/ (47M)\n\
+             ├── d (24M)\n\
+             │   ├── d.log (7.7M)\n\
+             │   ├── k (6.9M)\n\
+             │   ├── d.ext (5.4M)\n\
+             │   └── j (3.9M)\n\
+             ├── b.txt (15M)\n\
+             ├── c.dat (8.2M)\n\
+             └── a (93K)\n\
+             \x20   └── h.lst (62K)\n"
+        );
+        let options = ReportOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            tree(&fs, &options),
+            "/ (48381165)\n├── a (94853)\n├── b.txt (14848514)\n├── c.dat (8504156)\n└── d (24933642)\n"
+        );
+    }
+}
@@ -0,0 +1,540 @@
+use std::io::{BufRead, Read, Write};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use crate::report::{self, ReportOptions};
+use crate::transcript;
+use crate::vfs::{FsError, NodeId, NodeKind, VirtualFs};
+use crate::{Commands, ParseCommandError};
+
+const HELP: &str = "\
+cd [path]                 Change the current directory, to the root without a path
+ls [path]                 List a directory
+pwd                       Print the current directory
+du [-h] [path]            Print the total size of each directory
+find [path] -size [+-]N   Print the files bigger (+) or smaller (-) than N, or of exactly N
+rm [-r] path              Remove a file, or a directory with -r
+save file                 Write the transcript, typed commands included
+help                      Print this help
+exit                      Leave the shell
+";
+
+#[derive(Debug, PartialEq)]
+pub enum ShellError {
+    Usage(String),
+    //A line read as a transcript command, with its error
+    Parse(ParseCommandError),
+    Fs(FsError),
+    //Listing a directory whose entries are not all known
+    NotListed(String),
+    Io(String),
+}
+
+impl From<FsError> for ShellError {
+    fn from(error: FsError) -> Self {
+        ShellError::Fs(error)
+    }
+}
+
+/// What the shell should do after a line.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Print(String),
+    Exit,
+}
+
+/// A shell over the tree of a transcript. The commands the transcript can hold (`cd`, `ls`, `pwd`
+/// and `rm`) are appended to it as they are typed, so that saving and replaying it gives back the
+/// same tree.
+pub struct Shell {
+    fs: VirtualFs,
+    transcript: Vec<Commands>,
+}
+
+fn size_filter(filter: &str) -> Option<impl Fn(u32) -> bool> {
+    let (ordering, size) = match filter.as_bytes().first()? {
+        b'+' => (std::cmp::Ordering::Greater, &filter[1..]),
+        b'-' => (std::cmp::Ordering::Less, &filter[1..]),
+        _ => (std::cmp::Ordering::Equal, filter),
+    };
+    let size = report::parse_size(size)?;
+    Some(move |file_size: u32| (file_size as u64).cmp(&size) == ordering)
+}
+
+impl Shell {
+    pub fn new(transcript: Vec<Commands>) -> Result<Self, FsError> {
+        Ok(Shell {
+            fs: VirtualFs::from_commands(&transcript)?,
+            transcript,
+        })
+    }
+
+    pub fn transcript(&self) -> &[Commands] {
+        &self.transcript
+    }
+
+    /// Applies a command to the tree and records it.
+    fn execute(&mut self, command: Commands) -> Result<(), ShellError> {
+        self.fs.apply(&command)?;
+        self.transcript.push(command);
+        Ok(())
+    }
+
+    fn dir(&self, path: &str) -> Result<NodeId, ShellError> {
+        let id = self.fs.lookup(path)?;
+        if !self.fs.node(id).is_dir() {
+            return Err(FsError::NotADirectory(self.fs.path_of(id)).into());
+        }
+        Ok(id)
+    }
+
+    fn ls(&mut self) -> Result<String, ShellError> {
+        let cwd = self.fs.cwd();
+        if !self.fs.is_listed(cwd) {
+            return Err(ShellError::NotListed(self.fs.path_of(cwd)));
+        }
+        let command = transcript::listing(&self.fs, cwd);
+        let Commands::List { list } = &command else {
+            unreachable!("A listing is always a list command");
+        };
+        let output = list.iter().map(|entry| format!("{}\n", entry)).collect();
+        self.execute(command)?;
+        Ok(output)
+    }
+
+    fn find(&self, from: NodeId, keep: impl Fn(u32) -> bool) -> String {
+        let mut output = String::new();
+        let mut stack = vec![from];
+        while let Some(id) = stack.pop() {
+            match &self.fs.node(id).kind {
+                NodeKind::File { size } if keep(*size) => {
+                    output += &format!("{}\n", self.fs.path_of(id));
+                }
+                NodeKind::File { .. } => {}
+                NodeKind::Dir { children, .. } => stack.extend(children.values().rev()),
+            }
+        }
+        output
+    }
+
+    /// Runs the commands a transcript can hold, typed without their output.
+    fn run_command(&mut self, command: Commands) -> Result<String, ShellError> {
+        match command {
+            Commands::ChangeDirectory { path } => {
+                //Unlike in a transcript, a directory does not exist just because of a cd
+                if let Some(path) = &path {
+                    self.dir(path)?;
+                }
+                self.execute(Commands::ChangeDirectory { path })?;
+                Ok(String::new())
+            }
+            Commands::List { .. } => self.ls(),
+            Commands::PrintDirectory { .. } => {
+                let path = self.fs.path_of(self.fs.cwd());
+                self.execute(Commands::PrintDirectory {
+                    path: Some(path.clone()),
+                })?;
+                Ok(format!("{}\n", path))
+            }
+            command @ Commands::Remove { .. } => {
+                self.execute(command)?;
+                Ok(String::new())
+            }
+        }
+    }
+
+    pub fn run_line(&mut self, line: &str) -> Result<Outcome, ShellError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let output = match words.as_slice() {
+            [] => String::new(),
+            ["exit"] | ["quit"] => return Ok(Outcome::Exit),
+            ["help"] => HELP.to_string(),
+            ["ls", path] if !path.starts_with('-') => {
+                let id = self.fs.lookup(path)?;
+                if let NodeKind::File { size } = self.fs.node(id).kind {
+                    format!("{} {}\n", size, self.fs.node(id).name)
+                } else if !self.fs.is_listed(id) {
+                    return Err(ShellError::NotListed(self.fs.path_of(id)));
+                } else {
+                    //Recorded as going there, listing and coming back
+                    let back = self.fs.path_of(self.fs.cwd());
+                    self.execute(Commands::ChangeDirectory {
+                        path: Some(path.to_string()),
+                    })?;
+                    let output = self.ls()?;
+                    self.execute(Commands::ChangeDirectory { path: Some(back) })?;
+                    output
+                }
+            }
+            ["du", args @ ..] => {
+                let human = args.contains(&"-h");
+                let paths: Vec<&&str> = args.iter().filter(|arg| **arg != "-h").collect();
+                let from = match paths.as_slice() {
+                    [] => self.fs.cwd(),
+                    [path] => self.fs.lookup(path)?,
+                    _ => return Err(ShellError::Usage("du [-h] [path]".to_string())),
+                };
+                let options = ReportOptions {
+                    human,
+                    ..Default::default()
+                };
+                report::du(&self.fs, from, &options)
+            }
+            ["find", "-size", filter] | ["find", ".", "-size", filter] => {
+                let keep = size_filter(filter).ok_or_else(|| {
+                    ShellError::Usage(format!("Expecting a size instead of {}", filter))
+                })?;
+                self.find(self.fs.cwd(), keep)
+            }
+            ["find", path, "-size", filter] => {
+                let keep = size_filter(filter).ok_or_else(|| {
+                    ShellError::Usage(format!("Expecting a size instead of {}", filter))
+                })?;
+                self.find(self.fs.lookup(path)?, keep)
+            }
+            ["save", file] => {
+                std::fs::write(file, transcript::to_text(self.transcript()))
+                    .map_err(|error| ShellError::Io(format!("{}: {}", file, error)))?;
+                String::new()
+            }
+            ["find" | "save" | "help" | "exit" | "quit", ..] => {
+                return Err(ShellError::Usage(format!(
+                    "Unknown arguments for {}, try help",
+                    words[0]
+                )))
+            }
+            _ => {
+                let command =
+                    Commands::from_str(&format!("$ {}", line)).map_err(ShellError::Parse)?;
+                self.run_command(command)?
+            }
+        };
+        Ok(Outcome::Print(output))
+    }
+
+    /// The ways to complete the last word of the line with the name of a child, directories ending
+    /// with a `/`.
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let word = match line.rsplit_once(char::is_whitespace) {
+            Some((_, word)) => word,
+            None => line,
+        };
+        let (dir, prefix) = match word.rsplit_once('/') {
+            Some((dir, prefix)) => (format!("{}/", dir), prefix),
+            None => (String::new(), word),
+        };
+        let Ok(dir_id) = self.fs.lookup(if dir.is_empty() { "." } else { &dir }) else {
+            return Vec::new();
+        };
+        self.fs
+            .children(dir_id)
+            .into_iter()
+            .flatten()
+            .filter(|(name, _)| name.starts_with(prefix))
+            .map(|(name, &id)| {
+                let slash = if self.fs.node(id).is_dir() { "/" } else { "" };
+                format!("{}{}{}", dir, name, slash)
+            })
+            .collect()
+    }
+}
+
+fn common_prefix(words: &[String]) -> &str {
+    let first = &words[0];
+    let mut len = first.len();
+    for word in &words[1..] {
+        len = first
+            .char_indices()
+            .zip(word.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, c), _)| i + c.len_utf8())
+            .min(len);
+    }
+    &first[..len]
+}
+
+/// Reads a line typed in a terminal in raw mode, echoing it and completing words on tab. None when
+/// the input ends, or on Ctrl-D on an empty line. Ctrl-C starts the line over.
+pub fn edit_line(
+    input: &mut impl Iterator<Item = u8>,
+    output: &mut impl Write,
+    prompt: &str,
+    complete: impl Fn(&str) -> Vec<String>,
+) -> std::io::Result<Option<String>> {
+    let mut line: Vec<u8> = Vec::new();
+    write!(output, "{}", prompt)?;
+    output.flush()?;
+    while let Some(byte) = input.next() {
+        match byte {
+            b'\n' | b'\r' => {
+                writeln!(output)?;
+                return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+            }
+            //Ctrl-D
+            0x04 if line.is_empty() => return Ok(None),
+            //Ctrl-C drops the line, signals being off in raw mode
+            0x03 => {
+                line.clear();
+                writeln!(output, "^C")?;
+                write!(output, "{}", prompt)?;
+            }
+            //Backspace and delete remove a whole character
+            0x08 | 0x7f => {
+                while line
+                    .pop()
+                    .is_some_and(|byte| byte & 0b1100_0000 == 0b1000_0000)
+                {}
+                write!(output, "\x08 \x08")?;
+            }
+            b'\t' => {
+                let text = String::from_utf8_lossy(&line).into_owned();
+                let candidates = complete(&text);
+                if candidates.is_empty() {
+                    continue;
+                }
+                let word_len = text
+                    .rsplit_once(char::is_whitespace)
+                    .map_or(text.len(), |(_, word)| word.len());
+                let completion = common_prefix(&candidates);
+                let added = completion.get(word_len..).unwrap_or("");
+                line.extend(added.as_bytes());
+                if candidates.len() == 1 || !added.is_empty() {
+                    write!(output, "{}", added)?;
+                } else {
+                    writeln!(output)?;
+                    writeln!(output, "{}", candidates.join("  "))?;
+                    write!(output, "{}{}", prompt, String::from_utf8_lossy(&line))?;
+                }
+            }
+            //Escape sequences, like the arrows, are skipped
+            0x1b => {
+                input.next();
+                input.next();
+            }
+            byte if byte >= 0x20 => {
+                line.push(byte);
+                output.write_all(&[byte])?;
+            }
+            _ => {}
+        }
+        output.flush()?;
+    }
+    Ok(None)
+}
+
+/// Puts the terminal in raw mode with `stty` until dropped. Fails when the input is not a terminal.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn stty(args: &[&str]) -> Option<String> {
+        let output = Command::new("stty")
+            .args(args)
+            .stdin(Stdio::inherit())
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn enable() -> Option<Self> {
+        let saved = RawMode::stty(&["-g"])?;
+        //Without signals, Ctrl-C is read as a byte and can not leave the terminal without echo
+        RawMode::stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        RawMode::stty(&[&self.saved]);
+    }
+}
+
+/// Runs the shell on the standard input until `exit` or the end of the input. Completion is only
+/// available in a terminal, otherwise lines are read as they come.
+pub fn run(shell: &mut Shell) -> std::io::Result<()> {
+    const PROMPT: &str = "$ ";
+
+    let raw_mode = RawMode::enable();
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout();
+    loop {
+        let line = if raw_mode.is_some() {
+            let mut bytes = (&mut stdin).bytes().map_while(Result::ok);
+            edit_line(&mut bytes, &mut stdout, PROMPT, |line| shell.complete(line))?
+        } else {
+            let mut line = String::new();
+            match stdin.read_line(&mut line)? {
+                0 => None,
+                _ => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+            }
+        };
+        let Some(line) = line else {
+            break;
+        };
+        match shell.run_line(&line) {
+            Ok(Outcome::Print(output)) => write!(stdout, "{}", output)?,
+            Ok(Outcome::Exit) => break,
+            Err(error) => writeln!(stdout, "{:?}", error)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_input, TEST_INPUT};
+
+    fn test_shell() -> Shell {
+        Shell::new(parse_input(TEST_INPUT).unwrap()).unwrap()
+    }
+
+    fn print(shell: &mut Shell, line: &str) -> String {
+        match shell.run_line(line) {
+            Ok(Outcome::Print(output)) => output,
+            result => panic!("{}: {:?}", line, result),
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut shell = test_shell();
+        assert_eq!(print(&mut shell, "pwd"), "/d\n");
+        assert_eq!(print(&mut shell, "cd ../a"), "");
+        assert_eq!(
+            print(&mut shell, "ls"),
+            "dir e\n29116 f\n2557 g\n62596 h.lst\n"
+        );
+        assert_eq!(print(&mut shell, "ls e"), "584 i\n");
+        assert_eq!(print(&mut shell, "pwd"), "/a\n");
+        assert_eq!(print(&mut shell, "du -h"), "584\t/a/e\n93K\t/a\n");
+        assert_eq!(print(&mut shell, "find / -size +8M"), "/b.txt\n/c.dat\n");
+        assert_eq!(print(&mut shell, "find -size -3K"), "/a/e/i\n/a/g\n");
+        assert_eq!(print(&mut shell, "find . -size 584"), "/a/e/i\n");
+        assert_eq!(print(&mut shell, "rm -r e"), "");
+        assert_eq!(print(&mut shell, "cd"), "");
+        assert_eq!(
+            print(&mut shell, "du"),
+            "94269\t/a\n24933642\t/d\n48380581\t/\n"
+        );
+
+        assert_eq!(
+            shell.run_line("cd b.txt"),
+            Err(ShellError::Fs(FsError::NotADirectory("/b.txt".to_string())))
+        );
+        assert_eq!(
+            shell.run_line("cd x"),
+            Err(ShellError::Fs(FsError::NotFound("/x".to_string())))
+        );
+        assert_eq!(
+            shell.run_line("rm a"),
+            Err(ShellError::Fs(FsError::IsADirectory("/a".to_string())))
+        );
+        assert_eq!(
+            shell.run_line("mv a b"),
+            Err(ShellError::Parse(ParseCommandError::UnknownCommand {
+                line: 1,
+                command: "mv".to_string()
+            }))
+        );
+        assert_eq!(
+            shell.run_line("rm -f a"),
+            Err(ShellError::Parse(ParseCommandError::BadArgument {
+                line: 1,
+                argument: "-f".to_string()
+            }))
+        );
+        assert!(matches!(
+            shell.run_line("du a b"),
+            Err(ShellError::Usage(_))
+        ));
+        assert!(matches!(
+            shell.run_line("help me"),
+            Err(ShellError::Usage(_))
+        ));
+        assert_eq!(shell.run_line("exit"), Ok(Outcome::Exit));
+    }
+
+    #[test]
+    fn test_not_listed() {
+        let mut shell = Shell::new(parse_input("$ cd /a/b\n$ ls\n1 x").unwrap()).unwrap();
+        let transcript_len = shell.transcript().len();
+        assert_eq!(print(&mut shell, "ls"), "1 x\n");
+        print(&mut shell, "cd /");
+        assert_eq!(
+            shell.run_line("ls"),
+            Err(ShellError::NotListed("/".to_string()))
+        );
+        assert_eq!(
+            shell.run_line("ls a"),
+            Err(ShellError::NotListed("/a".to_string()))
+        );
+        assert_eq!(print(&mut shell, "ls a/b"), "1 x\n");
+        assert_eq!(print(&mut shell, "pwd"), "/\n");
+        //Only the commands that worked are recorded
+        assert_eq!(shell.transcript().len(), transcript_len + 6);
+    }
+
+    #[test]
+    fn test_transcript_replay() {
+        let mut shell = test_shell();
+        for line in [
+            "cd /a", "ls e", "rm g", "cd ..", "ls", "rm -r d", "cd a/e", "ls",
+        ] {
+            print(&mut shell, line);
+        }
+        let text = transcript::to_text(shell.transcript());
+        assert!(text.ends_with("$ rm -r d\n$ cd a/e\n$ ls\n584 i\n"));
+        let replayed = Shell::new(parse_input(&text).unwrap()).unwrap();
+        assert_eq!(replayed.fs, shell.fs);
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut shell = test_shell();
+        print(&mut shell, "cd /");
+        assert_eq!(shell.complete("cd "), vec!["a/", "b.txt", "c.dat", "d/"]);
+        assert_eq!(shell.complete("ls d/d."), vec!["d/d.ext", "d/d.log"]);
+        assert_eq!(shell.complete("cd a/e/"), vec!["a/e/i"]);
+        assert_eq!(shell.complete("cd /a/h"), vec!["/a/h.lst"]);
+        assert!(shell.complete("cd x/").is_empty());
+    }
+
+    #[test]
+    fn test_edit_line() {
+        let shell = test_shell();
+        let complete = |line: &str| shell.complete(line);
+        let mut input = "ls /d/d.\tl\too\x7f\x7f\nab\x1b[Dc\n\x04".bytes();
+        let mut output = Vec::new();
+        assert_eq!(
+            edit_line(&mut input, &mut output, "$ ", complete).unwrap(),
+            Some("ls /d/d.log".to_string())
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "$ ls /d/d.\n/d/d.ext  /d/d.log\n$ ls /d/d.logoo\x08 \x08\x08 \x08\n"
+        );
+        let mut output = Vec::new();
+        assert_eq!(
+            edit_line(&mut input, &mut output, "$ ", complete).unwrap(),
+            Some("abc".to_string())
+        );
+        assert_eq!(
+            edit_line(&mut input, &mut output, "$ ", complete).unwrap(),
+            None
+        );
+
+        let mut output = Vec::new();
+        assert_eq!(
+            edit_line(&mut "xy\x03z\n".bytes(), &mut output, "$ ", complete).unwrap(),
+            Some("z".to_string())
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), "$ xy^C\n$ z\n");
+    }
+}
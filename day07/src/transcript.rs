@@ -0,0 +1,567 @@
+use std::collections::VecDeque;
+use std::path::Path;
+
+use crate::vfs::{NodeId, NodeKind, VirtualFs};
+use crate::{Commands, ListTypes};
+
+#[derive(Debug, PartialEq)]
+pub enum SourceError {
+    Io(String),
+    //Position in the JSON text and what was expected there
+    Json(usize, String),
+    //A name a transcript can not read back, like a directory with a space or a name not in UTF-8
+    UnsupportedName(String),
+    TooBig(String),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Order {
+    DepthFirst,
+    BreadthFirst,
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscriptOptions {
+    pub order: Order,
+    //Go back to the root with `cd /` before moving to each directory
+    pub redundant_root_cd: bool,
+    //How many more times each directory is listed after its first listing
+    pub repeated_listings: usize,
+}
+
+impl Default for TranscriptOptions {
+    fn default() -> Self {
+        TranscriptOptions {
+            order: Order::DepthFirst,
+            redundant_root_cd: false,
+            repeated_listings: 0,
+        }
+    }
+}
+
+/// Whether a name can be written in a transcript and read back as a single path component.
+/// Listings join the words of a name with single spaces, but `cd` takes a single word.
+pub fn check_name(name: &str, is_dir: bool) -> Result<(), SourceError> {
+    let words: Vec<&str> = name.split_ascii_whitespace().collect();
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.contains('/')
+        || name.chars().any(char::is_control)
+        || words.join(" ") != name
+        || (is_dir && words.len() > 1)
+    {
+        return Err(SourceError::UnsupportedName(name.to_string()));
+    }
+    Ok(())
+}
+
+fn add_from_dir(fs: &mut VirtualFs, parent: NodeId, path: &Path) -> Result<(), SourceError> {
+    let entries = std::fs::read_dir(path)
+        .map_err(|error| SourceError::Io(format!("{}: {}", path.display(), error)))?;
+    for entry in entries {
+        let entry =
+            entry.map_err(|error| SourceError::Io(format!("{}: {}", path.display(), error)))?;
+        let entry_path = entry.path();
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| SourceError::UnsupportedName(name.to_string_lossy().into_owned()))?;
+        //Symbolic links are skipped, they could make the tree a loop
+        let metadata = std::fs::symlink_metadata(&entry_path)
+            .map_err(|error| SourceError::Io(format!("{}: {}", entry_path.display(), error)))?;
+        if metadata.is_dir() || metadata.is_file() {
+            check_name(&name, metadata.is_dir())?;
+        }
+        if metadata.is_dir() {
+            let id = fs.add_dir(parent, &name);
+            add_from_dir(fs, id, &entry_path)?;
+        } else if metadata.is_file() {
+            let size = u32::try_from(metadata.len())
+                .map_err(|_| SourceError::TooBig(entry_path.display().to_string()))?;
+            fs.add_file(parent, &name, size);
+        }
+    }
+    Ok(())
+}
+
+/// Reads the tree under a local directory, which becomes the root.
+pub fn from_dir(path: &Path) -> Result<VirtualFs, SourceError> {
+    let mut fs = VirtualFs::new();
+    add_from_dir(&mut fs, VirtualFs::ROOT, path)?;
+    Ok(fs)
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl JsonParser<'_> {
+    fn error<T>(&self, expected: &str) -> Result<T, SourceError> {
+        Err(SourceError::Json(
+            self.offset,
+            format!("Expected {}", expected),
+        ))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.text[self.offset..].starts_with(c) {
+            self.offset += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.text[self.offset..].chars().next()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    /// The four hexadecimal digits of a `\u` escape.
+    fn hex_digits(&mut self) -> Option<u32> {
+        let hex = self.text.get(self.offset..self.offset + 4)?;
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        self.offset += 4;
+        u32::from_str_radix(hex, 16).ok()
+    }
+
+    /// Reads what follows `\u`. Characters outside the basic plane are written as a surrogate
+    /// pair, two escapes in a row.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex_digits()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high);
+        }
+        if !self.text[self.offset..].starts_with("\\u") {
+            return None;
+        }
+        self.offset += 2;
+        let low = self.hex_digits()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return None;
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+    }
+
+    fn string(&mut self) -> Result<String, SourceError> {
+        if !self.eat('"') {
+            return self.error("a string");
+        }
+        let mut string = String::new();
+        loop {
+            let start = self.offset;
+            let c = match self.next_char() {
+                None => return self.error("the end of the string"),
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped = match self.next_char() {
+                        Some('"') => Some('"'),
+                        Some('\\') => Some('\\'),
+                        Some('/') => Some('/'),
+                        Some('b') => Some('\u{8}'),
+                        Some('f') => Some('\u{c}'),
+                        Some('n') => Some('\n'),
+                        Some('r') => Some('\r'),
+                        Some('t') => Some('\t'),
+                        Some('u') => match self.unicode_escape() {
+                            Some(c) => Some(c),
+                            None => {
+                                self.offset = start;
+                                return self.error("a valid unicode escape");
+                            }
+                        },
+                        _ => None,
+                    };
+                    match escaped {
+                        Some(c) => c,
+                        None => {
+                            self.offset = start;
+                            return self.error("a valid escape");
+                        }
+                    }
+                }
+                Some(c) if c < ' ' => {
+                    self.offset = start;
+                    return self.error("an escaped control character");
+                }
+                Some(c) => c,
+            };
+            string.push(c);
+        }
+    }
+
+    fn size(&mut self) -> Result<u32, SourceError> {
+        self.skip_whitespace();
+        let rest = &self.text[self.offset..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        match rest[..digits].parse() {
+            Ok(size) => {
+                self.offset += digits;
+                Ok(size)
+            }
+            Err(_) => self.error("a file size or a directory"),
+        }
+    }
+
+    /// Reads the entries of a directory, the opening brace being already read.
+    fn dir(&mut self, fs: &mut VirtualFs, id: NodeId) -> Result<(), SourceError> {
+        if self.eat('}') {
+            return Ok(());
+        }
+        loop {
+            let name = self.string()?;
+            if fs
+                .children(id)
+                .is_some_and(|children| children.contains_key(&name))
+            {
+                return Err(SourceError::Json(
+                    self.offset,
+                    format!("Duplicated name {}", name),
+                ));
+            }
+            if !self.eat(':') {
+                return self.error("':'");
+            }
+            let is_dir = self.eat('{');
+            check_name(&name, is_dir)?;
+            if is_dir {
+                let child = fs.add_dir(id, &name);
+                self.dir(fs, child)?;
+            } else {
+                let size = self.size()?;
+                fs.add_file(id, &name, size);
+            }
+            if self.eat('}') {
+                return Ok(());
+            }
+            if !self.eat(',') {
+                return self.error("',' or '}'");
+            }
+        }
+    }
+}
+
+/// Reads a tree described in JSON, where directories are objects and files are their sizes:
+/// `{"a": {"f": 29116}, "b.txt": 14848514}`.
+pub fn from_json(text: &str) -> Result<VirtualFs, SourceError> {
+    let mut parser = JsonParser { text, offset: 0 };
+    let mut fs = VirtualFs::new();
+    if !parser.eat('{') {
+        return parser.error("'{'");
+    }
+    parser.dir(&mut fs, VirtualFs::ROOT)?;
+    parser.skip_whitespace();
+    if parser.offset != text.len() {
+        return parser.error("the end of the text");
+    }
+    Ok(fs)
+}
+
+fn ancestors(fs: &VirtualFs, mut id: NodeId) -> Vec<NodeId> {
+    let mut ancestors = vec![id];
+    while let Some(parent) = fs.node(id).parent {
+        ancestors.push(parent);
+        id = parent;
+    }
+    ancestors.reverse();
+    ancestors
+}
+
+/// Moves from a directory to another one step at a time, through their closest common ancestor.
+fn navigate(fs: &VirtualFs, from: NodeId, to: NodeId, commands: &mut Vec<Commands>) {
+    let from_ancestors = ancestors(fs, from);
+    let to_ancestors = ancestors(fs, to);
+    let common = from_ancestors
+        .iter()
+        .zip(&to_ancestors)
+        .take_while(|(a, b)| a == b)
+        .count();
+    for _ in common..from_ancestors.len() {
+        commands.push(Commands::ChangeDirectory {
+            path: Some("..".to_string()),
+        });
+    }
+    for &id in &to_ancestors[common..] {
+        commands.push(Commands::ChangeDirectory {
+            path: Some(fs.node(id).name.clone()),
+        });
+    }
+}
+
+/// The listing of a directory, as `ls` shows it from inside.
+pub fn listing(fs: &VirtualFs, id: NodeId) -> Commands {
+    let children = fs.children(id).into_iter().flat_map(|c| c.values());
+    Commands::List {
+        list: children
+            .map(|&child| {
+                let node = fs.node(child);
+                match node.kind {
+                    NodeKind::File { size } => ListTypes::File {
+                        name: node.name.clone(),
+                        size,
+                    },
+                    NodeKind::Dir { .. } => ListTypes::Dir {
+                        name: node.name.clone(),
+                    },
+                }
+            })
+            .collect(),
+    }
+}
+
+/// The commands listing every directory of the tree, starting from the root.
+pub fn generate(fs: &VirtualFs, options: &TranscriptOptions) -> Vec<Commands> {
+    let sub_dirs = |id: NodeId| -> Vec<NodeId> {
+        fs.children(id)
+            .into_iter()
+            .flat_map(|c| c.values())
+            .copied()
+            .filter(|&child| fs.node(child).is_dir())
+            .collect()
+    };
+
+    //The directories in the order they are visited
+    let mut visits = Vec::new();
+    match options.order {
+        Order::DepthFirst => {
+            let mut stack = vec![VirtualFs::ROOT];
+            while let Some(id) = stack.pop() {
+                visits.push(id);
+                stack.extend(sub_dirs(id).into_iter().rev());
+            }
+        }
+        Order::BreadthFirst => {
+            let mut queue = VecDeque::from([VirtualFs::ROOT]);
+            while let Some(id) = queue.pop_front() {
+                visits.push(id);
+                queue.extend(sub_dirs(id));
+            }
+        }
+    }
+
+    let mut commands = vec![Commands::ChangeDirectory {
+        path: Some("/".to_string()),
+    }];
+    let mut cwd = VirtualFs::ROOT;
+    for id in visits {
+        if options.redundant_root_cd && id != VirtualFs::ROOT {
+            commands.push(Commands::ChangeDirectory {
+                path: Some("/".to_string()),
+            });
+            cwd = VirtualFs::ROOT;
+        }
+        navigate(fs, cwd, id, &mut commands);
+        cwd = id;
+        for _ in 0..=options.repeated_listings {
+            commands.push(listing(fs, id));
+        }
+    }
+    commands
+}
+
+/// Writes the commands in the terminal format read by `parse_input`.
+pub fn to_text(commands: &[Commands]) -> String {
+    commands
+        .iter()
+        .map(|command| format!("{}\n", command))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{replay, TEST_INPUT};
+
+    /// The paths and sizes of every node, whatever the order they were added in.
+    fn summary(fs: &VirtualFs) -> Vec<(String, bool, u64)> {
+        let total_sizes = fs.total_sizes();
+        let mut summary: Vec<_> = fs
+            .nodes()
+            .map(|(id, node)| (fs.path_of(id), node.is_dir(), total_sizes[id]))
+            .collect();
+        summary.sort();
+        summary
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let fs = replay(TEST_INPUT).unwrap();
+        for order in [Order::DepthFirst, Order::BreadthFirst] {
+            for redundant_root_cd in [false, true] {
+                for repeated_listings in [0, 2] {
+                    let options = TranscriptOptions {
+                        order,
+                        redundant_root_cd,
+                        repeated_listings,
+                    };
+                    let text = to_text(&generate(&fs, &options));
+                    assert_eq!(
+                        summary(&replay(&text).unwrap()),
+                        summary(&fs),
+                        "{:?}",
+                        options
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate() {
+        let fs = from_json(r#"{"a": {"e": {"i": 584}, "f": 29116}, "b": {}, "c.dat": 8504156}"#)
+            .unwrap();
+        let options = TranscriptOptions {
+            order: Order::BreadthFirst,
+            ..Default::default()
+        };
+        assert_eq!(
+            to_text(&generate(&fs, &options)),
+            "$ cd /\n$ ls\ndir a\ndir b\n8504156 c.dat\n$ cd a\n$ ls\ndir e\n29116 f\n\
+             $ cd ..\n$ cd b\n$ ls\n$ cd ..\n$ cd a\n$ cd e\n$ ls\n584 i\n"
+        );
+        let options = TranscriptOptions {
+            redundant_root_cd: true,
+            repeated_listings: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            to_text(&generate(&fs, &options)),
+            "$ cd /\n$ ls\ndir a\ndir b\n8504156 c.dat\n$ ls\ndir a\ndir b\n8504156 c.dat\n\
+             $ cd /\n$ cd a\n$ ls\ndir e\n29116 f\n$ ls\ndir e\n29116 f\n\
+             $ cd /\n$ cd a\n$ cd e\n$ ls\n584 i\n$ ls\n584 i\n\
+             $ cd /\n$ cd b\n$ ls\n$ ls\n"
+        );
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        assert_eq!(
+            from_json(r#"{"a": {"b": 1,}}"#),
+            Err(SourceError::Json(14, "Expected a string".to_string()))
+        );
+        assert_eq!(
+            from_json(r#"{"a b": {}}"#),
+            Err(SourceError::UnsupportedName("a b".to_string()))
+        );
+        assert_eq!(
+            from_json(r#"{"a": 1, "a": 2}"#),
+            Err(SourceError::Json(12, "Duplicated name a".to_string()))
+        );
+        assert_eq!(
+            from_json(r#"{"a": -1}"#),
+            Err(SourceError::Json(
+                6,
+                "Expected a file size or a directory".to_string()
+            ))
+        );
+        assert_eq!(
+            from_json(r#"{"a\"\\\u00e9\ud83d\ude00 b": 1}"#).map(|fs| summary(&fs)),
+            Ok(vec![
+                ("/".to_string(), true, 1),
+                ("/a\"\\é😀 b".to_string(), false, 1)
+            ])
+        );
+        //Escapes of control characters and slashes are read, but such names are not supported
+        for (json, name) in [
+            (r#"{"a\/b": 1}"#, "a/b"),
+            (r#"{" a": 1}"#, " a"),
+            (r#"{"a  b": 1}"#, "a  b"),
+            (r#"{"a\f": 1}"#, "a\u{c}"),
+            (r#"{"a\b": 1}"#, "a\u{8}"),
+            (r#"{"a\r": 1}"#, "a\r"),
+            (r#"{"a\n\t": 1}"#, "a\n\t"),
+        ] {
+            assert_eq!(
+                from_json(json),
+                Err(SourceError::UnsupportedName(name.to_string()))
+            );
+        }
+        for json in [
+            r#"{"a\ud83d": 1}"#,
+            r#"{"a\ud83d\u0041": 1}"#,
+            r#"{"a\ude00": 1}"#,
+            r#"{"a\u+041": 1}"#,
+            r#"{"a\u00": 1}"#,
+        ] {
+            assert_eq!(
+                from_json(json),
+                Err(SourceError::Json(
+                    3,
+                    "Expected a valid unicode escape".to_string()
+                )),
+                "{}",
+                json
+            );
+        }
+        assert_eq!(
+            from_json(r#"{"a\x": 1}"#),
+            Err(SourceError::Json(3, "Expected a valid escape".to_string()))
+        );
+        assert_eq!(
+            from_json("{\"a\u{1}\": 1}"),
+            Err(SourceError::Json(
+                3,
+                "Expected an escaped control character".to_string()
+            ))
+        );
+        assert_eq!(
+            from_json("{} {}"),
+            Err(SourceError::Json(
+                3,
+                "Expected the end of the text".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_from_dir() {
+        let root = std::env::temp_dir().join(format!("day07-transcript-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("a/e")).unwrap();
+        std::fs::write(root.join("a/e/i"), vec![0; 584]).unwrap();
+        std::fs::write(root.join("b.txt"), vec![0; 1000]).unwrap();
+        std::fs::write(root.join("python 2 sunset.rst"), vec![0; 10]).unwrap();
+        let fs = from_dir(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let fs = fs.unwrap();
+        let text = to_text(&generate(&fs, &TranscriptOptions::default()));
+        assert_eq!(
+            text,
+            "$ cd /\n$ ls\ndir a\n1000 b.txt\n10 python 2 sunset.rst\n$ cd a\n$ ls\ndir e\n\
+             $ cd e\n$ ls\n584 i\n"
+        );
+        assert_eq!(summary(&replay(&text).unwrap()), summary(&fs));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_dir_non_utf8_name() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let root =
+            std::env::temp_dir().join(format!("day07-transcript-utf8-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let created = std::fs::write(root.join(std::ffi::OsStr::from_bytes(b"a\xff")), b"");
+        let fs = from_dir(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        //Some filesystems only accept UTF-8 names
+        if created.is_ok() {
+            assert_eq!(
+                fs,
+                Err(SourceError::UnsupportedName("a\u{fffd}".to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::BTreeMap;
+
+use crate::{Commands, ListTypes};
+
+pub type NodeId = usize;
+
+#[derive(Debug, PartialEq)]
+pub enum NodeKind {
+    File {
+        size: u32,
+    },
+    //Whether the directory was listed, otherwise it is only known from a cd or its parent listing
+    Dir {
+        children: BTreeMap<String, NodeId>,
+        listed: bool,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Node {
+    pub name: String,
+    pub parent: Option<NodeId>,
+    pub kind: NodeKind,
+}
+
+impl Node {
+    pub fn is_dir(&self) -> bool {
+        matches!(self.kind, NodeKind::Dir { .. })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FsError {
+    NotFound(String),
+    NotADirectory(String),
+    //A listing that does not agree with what an earlier one showed
+    Conflict { path: String, reason: String },
+    //Removing a directory needs to be recursive
+    IsADirectory(String),
+    //Removing the root or a directory containing the current one
+    Busy(String),
+}
+
+/// The filesystem seen through a terminal transcript. Nodes live in an arena and refer to each
+/// other by index, the root being the first one.
+#[derive(Debug, PartialEq)]
+pub struct VirtualFs {
+    nodes: Vec<Node>,
+    cwd: NodeId,
+}
+
+impl VirtualFs {
+    pub const ROOT: NodeId = 0;
+
+    pub fn new() -> Self {
+        VirtualFs {
+            nodes: vec![Node {
+                name: "/".to_string(),
+                parent: None,
+                kind: NodeKind::Dir {
+                    children: BTreeMap::new(),
+                    listed: false,
+                },
+            }],
+            cwd: VirtualFs::ROOT,
+        }
+    }
+
+    pub fn from_commands(commands: &[Commands]) -> Result<Self, FsError> {
+        let mut fs = VirtualFs::new();
+        for command in commands {
+            fs.apply(command)?;
+        }
+        Ok(fs)
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().enumerate()
+    }
+
+    pub fn cwd(&self) -> NodeId {
+        self.cwd
+    }
+
+    pub fn children(&self, id: NodeId) -> Option<&BTreeMap<String, NodeId>> {
+        match &self.nodes[id].kind {
+            NodeKind::Dir { children, .. } => Some(children),
+            NodeKind::File { .. } => None,
+        }
+    }
+
+    /// Whether all the entries of a directory are known, from a listing of it.
+    pub fn is_listed(&self, id: NodeId) -> bool {
+        matches!(self.nodes[id].kind, NodeKind::Dir { listed: true, .. })
+    }
+
+    pub fn path_of(&self, id: NodeId) -> String {
+        let mut names = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.nodes[current].parent {
+            names.push(self.nodes[current].name.as_str());
+            current = parent;
+        }
+        names.reverse();
+        format!("/{}", names.join("/"))
+    }
+
+    /// Follows a path, absolute or relative to the current directory. `..` above the root stays on
+    /// the root, like in a shell. Missing directories are added on the way, unless their parent was
+    /// listed without them.
+    fn walk_creating(&mut self, path: &str) -> Result<NodeId, FsError> {
+        let mut current = if path.starts_with('/') {
+            VirtualFs::ROOT
+        } else {
+            self.cwd
+        };
+
+        for component in path.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => current = self.nodes[current].parent.unwrap_or(VirtualFs::ROOT),
+                name => {
+                    let children = self
+                        .children(current)
+                        .ok_or_else(|| FsError::NotADirectory(self.path_of(current)))?;
+                    current = match children.get(name) {
+                        Some(&child) => child,
+                        None if self.is_listed(current) => {
+                            return Err(FsError::Conflict {
+                                path: format!(
+                                    "{}/{}",
+                                    self.path_of(current).trim_end_matches('/'),
+                                    name
+                                ),
+                                reason: "Entered but missing from the listing of its directory"
+                                    .to_string(),
+                            })
+                        }
+                        None => self.add_node(current, name, true, 0),
+                    };
+                }
+            }
+        }
+        Ok(current)
+    }
+
+    fn add_node(&mut self, parent: NodeId, name: &str, is_dir: bool, size: u32) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            parent: Some(parent),
+            kind: if is_dir {
+                NodeKind::Dir {
+                    children: BTreeMap::new(),
+                    listed: false,
+                }
+            } else {
+                NodeKind::File { size }
+            },
+        });
+        if let NodeKind::Dir { children, .. } = &mut self.nodes[parent].kind {
+            children.insert(name.to_string(), id);
+        }
+        id
+    }
+
+    pub fn add_dir(&mut self, parent: NodeId, name: &str) -> NodeId {
+        self.add_node(parent, name, true, 0)
+    }
+
+    pub fn add_file(&mut self, parent: NodeId, name: &str, size: u32) -> NodeId {
+        self.add_node(parent, name, false, size)
+    }
+
+    /// Finds a node from a path, relative to the current directory unless absolute.
+    pub fn lookup(&self, path: &str) -> Result<NodeId, FsError> {
+        let mut current = if path.starts_with('/') {
+            VirtualFs::ROOT
+        } else {
+            self.cwd
+        };
+        for component in path.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => current = self.nodes[current].parent.unwrap_or(VirtualFs::ROOT),
+                name => {
+                    let children = self
+                        .children(current)
+                        .ok_or_else(|| FsError::NotADirectory(self.path_of(current)))?;
+                    current = *children.get(name).ok_or_else(|| {
+                        FsError::NotFound(format!(
+                            "{}/{}",
+                            self.path_of(current).trim_end_matches('/'),
+                            name
+                        ))
+                    })?;
+                }
+            }
+        }
+        Ok(current)
+    }
+
+    /// Changes the current directory. Directories that were never listed are created, since the
+    /// transcript shows they exist. Without a path, goes back to the root.
+    pub fn cd(&mut self, path: Option<&str>) -> Result<(), FsError> {
+        let target = self.walk_creating(path.unwrap_or("/"))?;
+        if !self.nodes[target].is_dir() {
+            return Err(FsError::NotADirectory(self.path_of(target)));
+        }
+        self.cwd = target;
+        Ok(())
+    }
+
+    /// Records the listing of the current directory. Listing a directory again is fine as long as
+    /// the listings agree.
+    pub fn add_listing(&mut self, list: &[ListTypes]) -> Result<(), FsError> {
+        let cwd = self.cwd;
+        let NodeKind::Dir { children, listed } = &self.nodes[cwd].kind else {
+            unreachable!("The current directory is always a directory");
+        };
+
+        //Directories can also be created by a cd before their parent is listed, so only a
+        //directory that was already listed can miss entries
+        if *listed {
+            let missing = children.iter().find(|(name, _)| {
+                !list.iter().any(|entry| match entry {
+                    ListTypes::File { name: listed, .. } | ListTypes::Dir { name: listed } => {
+                        listed == *name
+                    }
+                })
+            });
+            if let Some((_, &id)) = missing {
+                return Err(FsError::Conflict {
+                    path: self.path_of(id),
+                    reason: "Missing from a new listing of its directory".to_string(),
+                });
+            }
+        }
+
+        //Nothing is added before the whole listing is known to agree with the tree
+        let mut new_entries = Vec::new();
+        for entry in list {
+            let (name, is_dir, size) = match entry {
+                ListTypes::File { name, size } => (name, false, *size),
+                ListTypes::Dir { name } => (name, true, 0),
+            };
+            //Such names would make paths lead somewhere else than the entry
+            if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+                return Err(FsError::Conflict {
+                    path: format!("{}/{}", self.path_of(cwd).trim_end_matches('/'), name),
+                    reason: "Listed with a name that is not a single path component".to_string(),
+                });
+            }
+            //What the name was known as, from the tree or from earlier in the same listing
+            let known = match children.get(name) {
+                Some(&id) => match self.nodes[id].kind {
+                    NodeKind::File { size } => Some((false, size)),
+                    NodeKind::Dir { .. } => Some((true, 0)),
+                },
+                None => new_entries
+                    .iter()
+                    .find(|(new_name, _, _)| *new_name == name)
+                    .map(|&(_, is_dir, size)| (is_dir, size)),
+            };
+            let reason = match (known, is_dir) {
+                (None, _) => {
+                    new_entries.push((name, is_dir, size));
+                    continue;
+                }
+                (Some((true, _)), true) => continue,
+                (Some((false, old_size)), false) if old_size == size => continue,
+                (Some((false, old_size)), false) => {
+                    format!("Listed with a size of {} after {}", size, old_size)
+                }
+                (Some((true, _)), false) => "Listed as a file after a directory".to_string(),
+                (Some((false, _)), true) => "Listed as a directory after a file".to_string(),
+            };
+            return Err(FsError::Conflict {
+                path: format!("{}/{}", self.path_of(cwd).trim_end_matches('/'), name),
+                reason,
+            });
+        }
+
+        for (name, is_dir, size) in new_entries {
+            self.add_node(cwd, name, is_dir, size);
+        }
+        if let NodeKind::Dir { listed, .. } = &mut self.nodes[cwd].kind {
+            *listed = true;
+        }
+        Ok(())
+    }
+
+    fn is_inside(&self, mut id: NodeId, ancestor: NodeId) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.nodes[id].parent {
+                Some(parent) => id = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Removes a node and everything inside it. The arena is rebuilt without them, so the ids of
+    /// the nodes after it change.
+    pub fn remove(&mut self, id: NodeId, recursive: bool) -> Result<(), FsError> {
+        if self.is_inside(self.cwd, id) {
+            return Err(FsError::Busy(self.path_of(id)));
+        }
+        if !recursive && self.nodes[id].is_dir() {
+            return Err(FsError::IsADirectory(self.path_of(id)));
+        }
+
+        let parent = self.nodes[id].parent.expect("Only the root has no parent");
+        let name = self.nodes[id].name.clone();
+        if let NodeKind::Dir { children, .. } = &mut self.nodes[parent].kind {
+            children.remove(&name);
+        }
+
+        //Parents are before their children, so whether a parent is removed is already known
+        let mut new_ids: Vec<Option<NodeId>> = Vec::with_capacity(self.nodes.len());
+        let mut kept = Vec::with_capacity(self.nodes.len());
+        for (old_id, node) in std::mem::take(&mut self.nodes).into_iter().enumerate() {
+            let is_removed =
+                old_id == id || node.parent.is_some_and(|parent| new_ids[parent].is_none());
+            if is_removed {
+                new_ids.push(None);
+            } else {
+                new_ids.push(Some(kept.len()));
+                kept.push(node);
+            }
+        }
+        for node in &mut kept {
+            node.parent = node.parent.and_then(|parent| new_ids[parent]);
+            if let NodeKind::Dir { children, .. } = &mut node.kind {
+                for child in children.values_mut() {
+                    *child = new_ids[*child].expect("Children of kept nodes are kept");
+                }
+            }
+        }
+        self.nodes = kept;
+        self.cwd = new_ids[self.cwd].expect("The current directory is kept");
+        Ok(())
+    }
+
+    pub fn apply(&mut self, command: &Commands) -> Result<(), FsError> {
+        match command {
+            Commands::ChangeDirectory { path } => self.cd(path.as_deref()),
+            Commands::List { list } => self.add_listing(list),
+            Commands::Remove { path, recursive } => self.remove(self.lookup(path)?, *recursive),
+            Commands::PrintDirectory { path: Some(path) } if *path != self.path_of(self.cwd) => {
+                Err(FsError::Conflict {
+                    path: path.clone(),
+                    reason: format!("Printed while in {}", self.path_of(self.cwd)),
+                })
+            }
+            Commands::PrintDirectory { .. } => Ok(()),
+        }
+    }
+
+    /// Total size of every node, files included. Children are always pushed after their parent, so
+    /// going through the nodes backwards adds each size to its parent after it is complete. Files fit
+    /// in 32 bits but their totals may not.
+    pub fn total_sizes(&self) -> Vec<u64> {
+        let mut total_sizes: Vec<u64> = self
+            .nodes
+            .iter()
+            .map(|node| match node.kind {
+                NodeKind::File { size } => size as u64,
+                NodeKind::Dir { .. } => 0,
+            })
+            .collect();
+        for (id, node) in self.nodes.iter().enumerate().rev() {
+            if let Some(parent) = node.parent {
+                total_sizes[parent] += total_sizes[id];
+            }
+        }
+        total_sizes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay;
+
+    fn size_of(fs: &VirtualFs, path: &str) -> u64 {
+        fs.total_sizes()[fs.lookup(path).unwrap()]
+    }
+
+    #[test]
+    fn test_cd_paths() {
+        let fs = replay(
+            "$ cd /a/b\n$ ls\n10 x\n$ cd ../../c\n$ ls\ndir d\n20 y\n$ cd ./d/./..\n$ cd ../../..\n$ cd c/d\n$ ls\n5 z",
+        )
+        .unwrap();
+        assert_eq!(size_of(&fs, "/"), 35);
+        assert_eq!(size_of(&fs, "/a"), 10);
+        assert_eq!(size_of(&fs, "/c"), 25);
+        assert_eq!(fs.path_of(fs.lookup("/c/d/z").unwrap()), "/c/d/z");
+        //Lookups are relative to where the transcript ended
+        assert_eq!(fs.lookup("z"), fs.lookup("/c/d/z"));
+        assert_eq!(fs.lookup("../../a/b/x"), fs.lookup("/a/b/x"));
+        assert_eq!(
+            fs.lookup("/a/q"),
+            Err(FsError::NotFound("/a/q".to_string()))
+        );
+        assert_eq!(
+            fs.lookup("/a/b/x/w"),
+            Err(FsError::NotADirectory("/a/b/x".to_string()))
+        );
+
+        assert_eq!(
+            replay("$ cd /\n$ ls\n10 x\n$ cd x"),
+            Err(FsError::NotADirectory("/x".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_large_totals() {
+        let fs = replay("$ cd /\n$ ls\ndir a\n4000000000 x\n$ cd a\n$ ls\n4000000000 y").unwrap();
+        assert_eq!(size_of(&fs, "/a"), 4_000_000_000);
+        assert_eq!(size_of(&fs, "/"), 8_000_000_000);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut fs = replay(
+            "$ cd /\n$ ls\ndir a\n10 x\n$ cd a\n$ ls\ndir b\n5 y\n$ cd b\n$ ls\n1 z\n$ cd /\n$ rm x",
+        )
+        .unwrap();
+        assert_eq!(size_of(&fs, "/"), 6);
+        assert_eq!(
+            fs.remove(fs.lookup("/a").unwrap(), false),
+            Err(FsError::IsADirectory("/a".to_string()))
+        );
+        assert_eq!(
+            fs.remove(VirtualFs::ROOT, true),
+            Err(FsError::Busy("/".to_string()))
+        );
+        fs.remove(fs.lookup("/a/b").unwrap(), true).unwrap();
+        assert_eq!(fs.nodes().count(), 3);
+        assert_eq!(size_of(&fs, "/"), 5);
+        assert_eq!(fs.path_of(fs.lookup("a/y").unwrap()), "/a/y");
+        //Listing the directory again without the removed entries is not a conflict
+        fs.cd(Some("a")).unwrap();
+        fs.add_listing(&[ListTypes::File {
+            name: "y".to_string(),
+            size: 5,
+        }])
+        .unwrap();
+        assert_eq!(
+            fs.remove(fs.lookup("/a").unwrap(), true),
+            Err(FsError::Busy("/a".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_relisting() {
+        let listing = "$ ls\ndir a\n10 x\n";
+        let fs = replay(&format!("$ cd /\n{listing}$ cd a\n$ cd /\n{listing}")).unwrap();
+        assert_eq!(fs.nodes().count(), 3);
+        assert_eq!(size_of(&fs, "/"), 10);
+
+        //A directory entered before its parent is listed is not a conflict
+        let fs = replay("$ cd /a\n$ ls\n1 y\n$ cd /\n$ ls\ndir a\n10 x").unwrap();
+        assert_eq!(size_of(&fs, "/"), 11);
+
+        assert_eq!(
+            replay("$ cd /\n$ ls\n10 x\n$ ls\n11 x"),
+            Err(FsError::Conflict {
+                path: "/x".to_string(),
+                reason: "Listed with a size of 11 after 10".to_string()
+            })
+        );
+        assert_eq!(
+            replay("$ cd /\n$ ls\ndir x\n$ ls\n10 x"),
+            Err(FsError::Conflict {
+                path: "/x".to_string(),
+                reason: "Listed as a file after a directory".to_string()
+            })
+        );
+        for name in [".", "..", "a/../../b"] {
+            let mut fs = VirtualFs::new();
+            assert_eq!(
+                fs.add_listing(&[ListTypes::Dir {
+                    name: name.to_string()
+                }]),
+                Err(FsError::Conflict {
+                    path: format!("/{}", name),
+                    reason: "Listed with a name that is not a single path component".to_string()
+                })
+            );
+            assert_eq!(fs.nodes().count(), 1);
+        }
+        //Listing an entry twice is fine as long as both agree
+        let fs = replay("$ cd /\n$ ls\n10 x\ndir a\n10 x\ndir a").unwrap();
+        assert_eq!(fs.nodes().count(), 3);
+        assert_eq!(size_of(&fs, "/"), 10);
+        assert_eq!(
+            replay("$ cd /\n$ ls\n10 x\n20 x\ndir a\ndir a"),
+            Err(FsError::Conflict {
+                path: "/x".to_string(),
+                reason: "Listed with a size of 20 after 10".to_string()
+            })
+        );
+        assert_eq!(
+            replay("$ cd /\n$ ls\ndir a\n10 a"),
+            Err(FsError::Conflict {
+                path: "/a".to_string(),
+                reason: "Listed as a file after a directory".to_string()
+            })
+        );
+        //A directory can not be entered when its parent was listed without it
+        assert_eq!(
+            replay("$ cd /\n$ ls\n10 x\n$ cd b\n$ ls\n5 y"),
+            Err(FsError::Conflict {
+                path: "/b".to_string(),
+                reason: "Entered but missing from the listing of its directory".to_string()
+            })
+        );
+        //Inside a directory that was not listed, they are still added
+        let fs = replay("$ cd /\n$ ls\ndir a\n$ cd a/b/../c").unwrap();
+        assert_eq!(fs.path_of(fs.cwd()), "/a/c");
+        assert!(fs.lookup("/a/b").is_ok());
+        assert_eq!(
+            replay("$ cd /\n$ ls\n10 x\n1 y\n$ ls\n10 x"),
+            Err(FsError::Conflict {
+                path: "/y".to_string(),
+                reason: "Missing from a new listing of its directory".to_string()
+            })
+        );
+    }
+}
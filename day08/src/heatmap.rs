@@ -0,0 +1,242 @@
+use std::fmt::Write;
+
+use crate::sight::{self, ViewOptions};
+use crate::{grid_size, Grid};
+
+type Color = [u8; 3];
+
+const HIDDEN: Color = [40, 40, 40];
+const VISIBLE: Color = [120, 200, 80];
+const HIGHLIGHT: Color = [230, 30, 30];
+//From dark blue to yellow, like viridis
+const RAMP: [Color; 5] = [
+    [68, 1, 84],
+    [59, 82, 139],
+    [33, 145, 140],
+    [94, 201, 98],
+    [253, 231, 37],
+];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Layer {
+    Visibility,
+    Scenic,
+}
+
+/// The color of a value between 0 and 1 on the ramp.
+fn ramp(value: f64) -> Color {
+    let position = value.clamp(0.0, 1.0) * (RAMP.len() - 1) as f64;
+    let i = (position.floor() as usize).min(RAMP.len() - 2);
+    let t = position - i as f64;
+    let mut color = [0; 3];
+    for (channel, value) in color.iter_mut().enumerate() {
+        let (from, to) = (RAMP[i][channel] as f64, RAMP[i + 1][channel] as f64);
+        *value = (from + (to - from) * t).round() as u8;
+    }
+    color
+}
+
+/// The color of each tree, and where the best viewpoint is.
+struct Heatmap {
+    colors: Vec<Vec<Color>>,
+    best: Option<(usize, usize)>,
+    scores: Vec<Vec<usize>>,
+}
+
+impl Heatmap {
+    fn new(grid: &Grid, layer: Layer, options: &ViewOptions) -> Self {
+        let scores = sight::scenic_scores(grid, options);
+        let mut best = None;
+        let mut best_score = 0;
+        for (y, row) in scores.iter().enumerate() {
+            for (x, &score) in row.iter().enumerate() {
+                if best.is_none() || score > best_score {
+                    best = Some((x, y));
+                    best_score = score;
+                }
+            }
+        }
+
+        let colors = match layer {
+            Layer::Visibility => sight::visibility(grid, &options.directions)
+                .into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .map(|visible| if visible { VISIBLE } else { HIDDEN })
+                        .collect()
+                })
+                .collect(),
+            //Scores span orders of magnitude, so the ramp follows their logarithm
+            Layer::Scenic => {
+                let max = (best_score as f64).ln_1p();
+                scores
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|&score| {
+                                ramp(if max > 0.0 {
+                                    (score as f64).ln_1p() / max
+                                } else {
+                                    0.0
+                                })
+                            })
+                            .collect()
+                    })
+                    .collect()
+            }
+        };
+
+        Heatmap {
+            colors,
+            best,
+            scores,
+        }
+    }
+}
+
+/// A binary PPM image, with `scale` pixels per tree. The best viewpoint is framed in red, inside its
+/// own cell so the trees around it keep their color. At a scale below 4 the whole cell is red.
+pub fn to_ppm(grid: &Grid, layer: Layer, options: &ViewOptions, scale: usize) -> Vec<u8> {
+    let scale = scale.max(1);
+    let heatmap = Heatmap::new(grid, layer, options);
+    let (n_rows, n_columns) = grid_size(grid);
+    let (width, height) = (n_columns * scale, n_rows * scale);
+
+    let mut pixels = vec![[0; 3]; width * height];
+    for (y, row) in heatmap.colors.iter().enumerate() {
+        for (x, &color) in row.iter().enumerate() {
+            for py in y * scale..(y + 1) * scale {
+                pixels[py * width + x * scale..py * width + (x + 1) * scale].fill(color);
+            }
+        }
+    }
+
+    if let Some((x, y)) = heatmap.best {
+        let thickness = (scale / 4).max(1);
+        for dy in 0..scale {
+            for dx in 0..scale {
+                //Distance to the nearest edge of the cell
+                let distance = dx.min(dy).min(scale - 1 - dx).min(scale - 1 - dy);
+                if distance < thickness {
+                    pixels[(y * scale + dy) * width + x * scale + dx] = HIGHLIGHT;
+                }
+            }
+        }
+    }
+
+    let mut image = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    image.extend(pixels.iter().flatten());
+    image
+}
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// An SVG image where each tree is a square cell, with its height and score as a tooltip.
+pub fn to_svg(grid: &Grid, layer: Layer, options: &ViewOptions, cell_size: usize) -> String {
+    let cell_size = cell_size.max(1);
+    let heatmap = Heatmap::new(grid, layer, options);
+    let (n_rows, n_columns) = grid_size(grid);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" shape-rendering=\"crispEdges\">\n",
+        n_columns * cell_size,
+        n_rows * cell_size
+    );
+    for (y, row) in heatmap.colors.iter().enumerate() {
+        for (x, &color) in row.iter().enumerate() {
+            writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{size}\" height=\"{size}\" fill=\"{}\"><title>({}, {}) height {} score {}</title></rect>",
+                x * cell_size,
+                y * cell_size,
+                hex(color),
+                x,
+                y,
+                grid[y][x].height,
+                heatmap.scores[y][x],
+                size = cell_size
+            )
+            .unwrap();
+        }
+    }
+    if let Some((x, y)) = heatmap.best {
+        writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"{size}\" height=\"{size}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>",
+            x * cell_size,
+            y * cell_size,
+            hex(HIGHLIGHT),
+            (cell_size / 4).max(1),
+            size = cell_size
+        )
+        .unwrap();
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_grid_from_string, TEST_INPUT};
+
+    #[test]
+    fn test_ramp() {
+        assert_eq!(ramp(0.0), RAMP[0]);
+        assert_eq!(ramp(1.0), RAMP[4]);
+        assert_eq!(ramp(0.5), RAMP[2]);
+        assert_eq!(ramp(0.125), [64, 42, 112]);
+    }
+
+    #[test]
+    fn test_to_ppm() {
+        let grid = build_grid_from_string(TEST_INPUT).unwrap();
+        let image = to_ppm(&grid, Layer::Visibility, &ViewOptions::default(), 8);
+        let header = b"P6\n40 40\n255\n";
+        assert_eq!(&image[..header.len()], header);
+        let pixels = &image[header.len()..];
+        assert_eq!(pixels.len(), 40 * 40 * 3);
+        let pixel = |x: usize, y: usize| -> Color {
+            let i = (y * 40 + x) * 3;
+            [pixels[i], pixels[i + 1], pixels[i + 2]]
+        };
+        //The tree at (1, 1) is visible, the one at (3, 1) is not
+        assert_eq!(pixel(12, 12), VISIBLE);
+        assert_eq!(pixel(24, 8), HIDDEN);
+        //The best viewpoint is the tree at (2, 3), framed two pixels thick inside its cell
+        assert_eq!(pixel(16, 24), HIGHLIGHT);
+        assert_eq!(pixel(23, 31), HIGHLIGHT);
+        assert_eq!(pixel(17, 28), HIGHLIGHT);
+        assert_eq!(pixel(20, 30), HIGHLIGHT);
+        assert_eq!(pixel(18, 26), VISIBLE);
+        assert_eq!(pixel(21, 29), VISIBLE);
+        //The hidden trees around it are left alone
+        assert_eq!(pixel(15, 28), HIDDEN);
+        assert_eq!(pixel(24, 28), HIDDEN);
+        assert_eq!(pixel(20, 23), HIDDEN);
+
+        //At a scale of 1 the frame is the tree itself
+        let image = to_ppm(&grid, Layer::Visibility, &ViewOptions::default(), 1);
+        let pixels = &image[b"P6\n5 5\n255\n".len()..];
+        assert_eq!(pixels[(3 * 5 + 2) * 3..(3 * 5 + 3) * 3], HIGHLIGHT);
+        assert_eq!(pixels[(3 * 5 + 1) * 3..(3 * 5 + 2) * 3], HIDDEN);
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let grid = build_grid_from_string(TEST_INPUT).unwrap();
+        let svg = to_svg(&grid, Layer::Scenic, &ViewOptions::default(), 10);
+        assert!(
+            svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"50\" height=\"50\"")
+        );
+        assert_eq!(svg.matches("<rect").count(), 26);
+        //The best tree gets the top of the ramp, edge trees the bottom
+        assert!(svg.contains("fill=\"#fde725\"><title>(2, 3) height 5 score 8</title>"));
+        assert!(svg.contains("fill=\"#440154\"><title>(0, 0) height 3 score 0</title>"));
+        assert!(svg.contains(
+            "<rect x=\"20\" y=\"30\" width=\"10\" height=\"10\" fill=\"none\" stroke=\"#e61e1e\""
+        ));
+    }
+}
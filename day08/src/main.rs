@@ -1,139 +1,313 @@
 use std::cmp::max;
 
+mod heatmap;
+mod sight;
+mod sites;
+
+use crate::heatmap::Layer;
+use crate::sight::{Combination, Directions, ViewOptions};
+use crate::sites::{Site, SiteFilter};
+
 type Grid = Vec<Vec<Tree>>;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 struct Tree {
-    x: usize,
-    y: usize,
     height: usize,
 }
 
-impl Tree {
-    fn is_visible(&self, grid: &Grid) -> bool {
-        let n_rows = grid.len();
-        let n_columns = grid.first().unwrap().len();
+fn grid_size(grid: &Grid) -> (usize, usize) {
+    (grid.len(), grid.first().map_or(0, |row| row.len()))
+}
 
-        if self.x == 0 || self.x == n_columns - 1 || self.y == 0 || self.y == n_rows - 1 {
-            return true;
-        }
+#[derive(Debug, PartialEq)]
+enum ParseGridError {
+    Empty,
+    //A row with another number of trees than the first one
+    Ragged {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    //Columns count characters from 1
+    InvalidHeight {
+        line: usize,
+        column: usize,
+        height: String,
+    },
+}
 
-        let directions = [
-            (0..self.x).rev().map(|x| (x, self.y)).collect::<Vec<_>>(),
-            (self.x + 1..n_columns).map(|x| (x, self.y)).collect(),
-            (0..self.y).rev().map(|y| (self.x, y)).collect(),
-            (self.y + 1..n_rows).map(|y| (self.x, y)).collect(),
-        ];
+fn is_separator(char: char) -> bool {
+    char == ',' || char.is_whitespace()
+}
 
-        for (cardinal, others) in directions.iter().enumerate() {
-            for other_pos in others.iter() {
-                let other_tree = &grid[other_pos.1][other_pos.0];
-                if self.height <= other_tree.height {
-                    break;
-                } else if (cardinal == 0 && other_pos.0 == 0)
-                    || (cardinal == 1 && other_pos.0 == n_columns - 1)
-                    || (cardinal == 2 && other_pos.1 == 0)
-                    || (cardinal == 3 && other_pos.1 == n_rows - 1)
-                {
-                    return true;
-                }
-            }
-        }
-        false
+/// Parses a height map, either as rows of digits or as rows of heights separated by commas or
+/// whitespace, which may then have several digits. Blank lines at the end are ignored.
+fn build_grid_from_string(input: &str) -> Result<Grid, ParseGridError> {
+    let mut lines: Vec<&str> = input.lines().collect();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
     }
+    if lines.is_empty() {
+        return Err(ParseGridError::Empty);
+    }
+    let separated = lines.iter().any(|line| line.trim().contains(is_separator));
 
-    fn visibility_score(&self, grid: &Grid) -> i32 {
-        let n_rows = grid.len();
-        let n_columns = grid.first().unwrap().len();
-
-        let directions = [
-            (0..self.x).rev().map(|x| (x, self.y)).collect::<Vec<_>>(),
-            (self.x + 1..n_columns).map(|x| (x, self.y)).collect(),
-            (0..self.y).rev().map(|y| (self.x, y)).collect(),
-            (self.y + 1..n_rows).map(|y| (self.x, y)).collect(),
-        ];
-
-        let mut view = 1;
-        for others in directions.iter() {
-            let mut cardinal_view = 0;
-            for other_pos in others.iter() {
-                let other_tree = &grid[other_pos.1][other_pos.0];
-                cardinal_view += 1;
-                if self.height <= other_tree.height {
-                    break;
+    let mut grid: Grid = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        //Each height with the column it starts at
+        let heights: Vec<(usize, &str)> = if separated {
+            let mut heights = vec![];
+            let mut start = None;
+            for (column, (offset, char)) in
+                line.char_indices().chain([(line.len(), ' ')]).enumerate()
+            {
+                match (start, is_separator(char)) {
+                    (None, false) => start = Some((column, offset)),
+                    (Some((start_column, start_offset)), true) => {
+                        heights.push((start_column, &line[start_offset..offset]));
+                        start = None;
+                    }
+                    _ => {}
                 }
             }
-            view *= cardinal_view
-        }
-
-        view
-    }
-}
+            heights
+        } else {
+            line.char_indices()
+                .enumerate()
+                .map(|(column, (offset, char))| (column, &line[offset..offset + char.len_utf8()]))
+                .collect()
+        };
 
-fn build_grid_from_string(input: &str) -> Grid {
-    let mut grid: Grid = vec![];
-    for (y, line) in input.lines().enumerate() {
         let mut row = vec![];
-        for (x, char) in line.chars().enumerate() {
-            row.push(Tree {
-                height: char.to_digit(10).unwrap().try_into().unwrap(),
-                x,
-                y,
-            });
+        for (column, height) in heights {
+            match height.parse() {
+                Ok(height) => row.push(Tree { height }),
+                Err(_) => {
+                    return Err(ParseGridError::InvalidHeight {
+                        line: i + 1,
+                        column: column + 1,
+                        height: height.to_string(),
+                    })
+                }
+            }
+        }
+        if let Some(first) = grid.first() {
+            if row.len() != first.len() {
+                return Err(ParseGridError::Ragged {
+                    line: i + 1,
+                    expected: first.len(),
+                    found: row.len(),
+                });
+            }
         }
         grid.push(row);
     }
-    grid
+    Ok(grid)
 }
 
-//Could be optimized by dyanic programming, each tree can store the max height seen in any
-//direction instread of looking it up every time
-fn get_answer_a(input: &str) -> i32 {
-    let grid = build_grid_from_string(input);
+/// The visibility as `O` for visible trees and `X` for hidden ones, then the heights.
+fn render_text(grid: &Grid, directions: &Directions) -> String {
+    let visible = sight::visibility(grid, directions);
     let mut visibility_grid = String::new();
     let mut height_grid = String::new();
 
-    let mut counter = 0;
-    for row in grid.iter() {
+    for (row, visible_row) in grid.iter().zip(&visible) {
         let mut vis_row = String::new();
         let mut height_row = String::new();
-        for tree in row.iter() {
+        for (tree, is_visible) in row.iter().zip(visible_row) {
             height_row += &format!("{}", tree.height);
-            if tree.is_visible(&grid) {
-                vis_row += "O";
-                counter += 1;
-            } else {
-                vis_row += "X";
-            }
+            vis_row += if *is_visible { "O" } else { "X" };
         }
         visibility_grid += &(vis_row + "\n");
         height_grid += &(height_row + "\n");
     }
-    println!("{}", visibility_grid);
-    println!();
-    println!("{}", height_grid);
-    counter
+    format!("{}\n\n{}", visibility_grid, height_grid)
 }
 
-fn get_answer_b(input: &str) -> i32 {
-    let grid = build_grid_from_string(input);
+fn get_answer_a(grid: &Grid, directions: &Directions) -> i32 {
+    sight::visibility(grid, directions)
+        .iter()
+        .flatten()
+        .filter(|is_visible| **is_visible)
+        .count() as i32
+}
 
+fn get_answer_b(grid: &Grid, options: &ViewOptions) -> i32 {
     let mut max_view = 0;
-    for row in grid.iter() {
-        for tree in row.iter() {
-            max_view = max(max_view, tree.visibility_score(&grid));
+    for row in sight::scenic_scores(grid, options) {
+        for score in row {
+            max_view = max(max_view, i32::try_from(score).unwrap_or(i32::MAX));
         }
     }
 
     max_view
 }
 
+fn format_site(site: &Site) -> String {
+    format!(
+        "({}, {}) height {} score {} distances {:?}",
+        site.x, site.y, site.height, site.score, site.distances
+    )
+}
+
 fn main() {
+    let mut print = false;
+    let mut ppm_path = None;
+    let mut svg_path = None;
+    let mut layer = Layer::Scenic;
+    let mut scale = 1;
+    let mut options = ViewOptions::default();
+    let mut top = None;
+    let mut select = None;
+    let mut filter = SiteFilter::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--print" => print = true,
+            "--ppm" => ppm_path = Some(args.next().expect("Expecting a file after --ppm")),
+            "--svg" => svg_path = Some(args.next().expect("Expecting a file after --svg")),
+            "--layer" => {
+                layer = match args.next().as_deref() {
+                    Some("visibility") => Layer::Visibility,
+                    Some("scenic") => Layer::Scenic,
+                    _ => panic!("Expecting visibility or scenic after --layer"),
+                }
+            }
+            "--scale" => {
+                scale = args
+                    .next()
+                    .and_then(|scale| scale.parse().ok())
+                    .expect("Expecting a number of pixels after --scale")
+            }
+            "--directions" => {
+                options.directions = match args.next().as_deref() {
+                    Some("cardinal") => Directions::Cardinal,
+                    Some("diagonal") => Directions::Diagonal,
+                    Some("all") => Directions::All,
+                    Some(rays) => Directions::Rays(
+                        rays.strip_prefix("rays:")
+                            .and_then(|n_rays| n_rays.parse().ok())
+                            .filter(|&n_rays| n_rays > 0)
+                            .expect(
+                                "Expecting cardinal, diagonal, all or rays:N after --directions",
+                            ),
+                    ),
+                    None => {
+                        panic!("Expecting cardinal, diagonal, all or rays:N after --directions")
+                    }
+                }
+            }
+            "--combine" => {
+                options.combination = match args.next().as_deref() {
+                    Some("product") => Combination::Product,
+                    Some("sum") => Combination::Sum,
+                    Some("min") => Combination::Min,
+                    Some("max") => Combination::Max,
+                    _ => panic!("Expecting product, sum, min or max after --combine"),
+                }
+            }
+            "--top" => {
+                top = Some(
+                    args.next()
+                        .and_then(|k| k.parse().ok())
+                        .expect("Expecting a number of sites after --top"),
+                )
+            }
+            "--select" => {
+                select = Some(
+                    args.next()
+                        .and_then(|k| k.parse().ok())
+                        .expect("Expecting a number of sites after --select"),
+                )
+            }
+            "--min-height" => {
+                filter.min_height = args
+                    .next()
+                    .and_then(|height| height.parse().ok())
+                    .expect("Expecting a height after --min-height")
+            }
+            "--no-edges" => filter.exclude_edges = true,
+            "--min-spacing" => {
+                filter.min_spacing = args
+                    .next()
+                    .and_then(|spacing| spacing.parse().ok())
+                    .expect("Expecting a number of trees after --min-spacing")
+            }
+            arg => panic!("Unknown argument: {}", arg),
+        }
+    }
+
     let input_path = "input/input.txt";
     let input = std::fs::read_to_string(input_path).unwrap();
 
-    println!("Answer A: {}", get_answer_a(&input));
-    println!("Answer B: {}", get_answer_b(&input));
+    let grid = build_grid_from_string(&input).expect("Expecting a valid height map");
+
+    if print || ppm_path.is_some() || svg_path.is_some() {
+        if print {
+            println!("{}", render_text(&grid, &options.directions));
+        }
+        if let Some(path) = ppm_path {
+            std::fs::write(path, heatmap::to_ppm(&grid, layer, &options, scale))
+                .expect("Expecting a writable image file");
+        }
+        if let Some(path) = svg_path {
+            std::fs::write(path, heatmap::to_svg(&grid, layer, &options, scale))
+                .expect("Expecting a writable image file");
+        }
+    }
+
+    if let Some(k) = top {
+        for site in sites::top_k(&grid, &options, &filter, k) {
+            println!("{}", format_site(&site));
+        }
+    }
+    if let Some(k) = select {
+        match sites::select(&grid, &options, &filter, k, sites::MAX_NODES) {
+            Some(selection) => {
+                for site in &selection.sites {
+                    println!("{}", format_site(site));
+                }
+                println!(
+                    "Total score: {}{}",
+                    selection.total,
+                    if selection.exact {
+                        ""
+                    } else {
+                        " (not proven best)"
+                    }
+                );
+            }
+            None => println!("No {} non-adjacent sites found", k),
+        }
+    }
+
+    println!("Answer A: {}", get_answer_a(&grid, &options.directions));
+    println!("Answer B: {}", get_answer_b(&grid, &options));
+}
+
+#[cfg(test)]
+const TEST_INPUT: &str = "30373\n\
+                          25512\n\
+                          65332\n\
+                          33549\n\
+                          35390\n";
+
+/// A pseudo random forest, from a linear congruential generator. The seed moves on, so that
+/// successive forests differ.
+#[cfg(test)]
+fn random_input(n_rows: usize, n_columns: usize, seed: &mut u64) -> String {
+    let mut input = String::new();
+    for _ in 0..n_rows {
+        for _ in 0..n_columns {
+            *seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            input += &((*seed >> 33) % 10).to_string();
+        }
+        input += "\n";
+    }
+    input
 }
 
 #[cfg(test)]
@@ -143,12 +317,150 @@ mod tests {
 
     #[test]
     fn test_get_answer_a() {
-        let test_input: &str = "30373\n\
-                                25512\n\
-                                65332\n\
-                                33549\n\
-                                35390\n";
+        assert_eq!(
+            get_answer_a(
+                &build_grid_from_string(TEST_INPUT).unwrap(),
+                &Directions::Cardinal
+            ),
+            21
+        );
+    }
+
+    #[test]
+    fn test_build_grid_errors() {
+        assert_eq!(build_grid_from_string(""), Err(ParseGridError::Empty));
+        assert_eq!(build_grid_from_string("\n  \n"), Err(ParseGridError::Empty));
+        assert_eq!(
+            build_grid_from_string("303\n25\n653\n"),
+            Err(ParseGridError::Ragged {
+                line: 2,
+                expected: 3,
+                found: 2,
+            })
+        );
+        assert_eq!(
+            build_grid_from_string("303\n\n653\n"),
+            Err(ParseGridError::Ragged {
+                line: 2,
+                expected: 3,
+                found: 0,
+            })
+        );
+        assert_eq!(
+            build_grid_from_string("303\n2a5\n"),
+            Err(ParseGridError::InvalidHeight {
+                line: 2,
+                column: 2,
+                height: "a".to_string(),
+            })
+        );
+        assert_eq!(
+            build_grid_from_string("10, 2, 3\n4, -5, 6\n"),
+            Err(ParseGridError::InvalidHeight {
+                line: 2,
+                column: 4,
+                height: "-5".to_string(),
+            })
+        );
+    }
 
-        assert_eq!(get_answer_a(test_input), 21);
+    #[test]
+    fn test_build_grid_separated() {
+        let heights = |grid: Grid| -> Vec<Vec<usize>> {
+            grid.iter()
+                .map(|row| row.iter().map(|tree| tree.height).collect())
+                .collect()
+        };
+        let expected = vec![vec![12, 0, 7], vec![3, 150, 3]];
+        for input in [
+            "12 0 7\n3 150 3\n",
+            "12,0,7\r\n3,150,3\r\n\n",
+            "  12,  0,\t7\n3 , 150 ,3",
+        ] {
+            assert_eq!(
+                heights(build_grid_from_string(input).unwrap()),
+                expected,
+                "{:?}",
+                input
+            );
+        }
+        //Digits next to each other are separate trees without separators
+        assert_eq!(
+            heights(build_grid_from_string("120\n735\n").unwrap()),
+            vec![vec![1, 2, 0], vec![7, 3, 5]]
+        );
+        //Multi-digit heights still follow the puzzle's rules
+        let grid = build_grid_from_string("10 10 10\n10 12 10\n10 11 10\n").unwrap();
+        assert_eq!(get_answer_a(&grid, &Directions::Cardinal), 9);
+        assert_eq!(get_answer_b(&grid, &ViewOptions::default()), 1);
+    }
+
+    #[test]
+    fn test_get_answer_b() {
+        assert_eq!(
+            get_answer_b(
+                &build_grid_from_string(TEST_INPUT).unwrap(),
+                &ViewOptions::default()
+            ),
+            8
+        );
+    }
+
+    //The first implementation, going through every direction from every tree
+    fn naive_is_visible(grid: &Grid, x: usize, y: usize) -> bool {
+        let height = grid[y][x].height;
+        let (n_rows, n_columns) = grid_size(grid);
+        let directions = [
+            (0..x).rev().map(|x| (x, y)).collect::<Vec<_>>(),
+            (x + 1..n_columns).map(|x| (x, y)).collect(),
+            (0..y).rev().map(|y| (x, y)).collect(),
+            (y + 1..n_rows).map(|y| (x, y)).collect(),
+        ];
+        directions
+            .iter()
+            .any(|others| others.iter().all(|&(x, y)| grid[y][x].height < height))
+    }
+
+    fn naive_distances(grid: &Grid, x: usize, y: usize) -> [usize; 4] {
+        let height = grid[y][x].height;
+        let (n_rows, n_columns) = grid_size(grid);
+        let directions = [
+            (0..x).rev().map(|x| (x, y)).collect::<Vec<_>>(),
+            (x + 1..n_columns).map(|x| (x, y)).collect(),
+            (0..y).rev().map(|y| (x, y)).collect(),
+            (y + 1..n_rows).map(|y| (x, y)).collect(),
+        ];
+        directions.map(|others| {
+            let mut distance = 0;
+            for &(x, y) in &others {
+                distance += 1;
+                if grid[y][x].height >= height {
+                    break;
+                }
+            }
+            distance
+        })
+    }
+
+    #[test]
+    fn test_sweeps_match_naive() {
+        //Pseudo random forests of a few shapes
+        let mut seed: u64 = 8;
+        let mut inputs = vec![TEST_INPUT.to_string()];
+        for (n_rows, n_columns) in [(1, 1), (1, 7), (6, 1), (9, 9), (13, 20), (30, 17)] {
+            inputs.push(random_input(n_rows, n_columns, &mut seed));
+        }
+
+        for input in inputs {
+            let grid = build_grid_from_string(&input).unwrap();
+            let visible = sight::visibility(&grid, &Directions::Cardinal);
+            let distances = sight::viewing_distances(&grid, &Directions::Cardinal);
+            for y in 0..grid.len() {
+                for x in 0..grid[y].len() {
+                    assert_eq!(visible[y][x], naive_is_visible(&grid, x, y), "{}", input);
+                    assert_eq!(distances[y][x], naive_distances(&grid, x, y), "{}", input);
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,366 @@
+use crate::{grid_size, Grid};
+
+/// A move from a tree to its neighbour, as a change of x and y. y grows downwards.
+pub type Step = (isize, isize);
+
+//Looking left, right, up and down
+pub const CARDINAL: [Step; 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+//Looking up left, up right, down left and down right
+pub const DIAGONAL: [Step; 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Directions {
+    Cardinal,
+    Diagonal,
+    //Cardinal then diagonal
+    All,
+    //This many rays at evenly spaced angles, counterclockwise from the right, traced with
+    //Bresenham's algorithm
+    Rays(usize),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Combination {
+    Product,
+    Sum,
+    Min,
+    Max,
+}
+
+impl Combination {
+    pub fn combine(&self, distances: &[usize]) -> usize {
+        match self {
+            //Many rays make products that no longer fit
+            Combination::Product => distances.iter().fold(1, |product: usize, &distance| {
+                product.saturating_mul(distance)
+            }),
+            Combination::Sum => distances.iter().sum(),
+            Combination::Min => distances.iter().copied().min().unwrap_or(0),
+            Combination::Max => distances.iter().copied().max().unwrap_or(0),
+        }
+    }
+}
+
+/// How trees look around them. The default is the puzzle's: the four cardinal directions, with
+/// the scenic score as the product of the viewing distances.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ViewOptions {
+    pub directions: Directions,
+    pub combination: Combination,
+}
+
+impl Default for ViewOptions {
+    fn default() -> Self {
+        ViewOptions {
+            directions: Directions::Cardinal,
+            combination: Combination::Product,
+        }
+    }
+}
+
+/// The lines of the grid along a step, each one starting from the edge the step goes towards:
+/// going through a line, the trees already seen are the ones in the direction of the step.
+fn lines_towards(n_rows: usize, n_columns: usize, (dx, dy): Step) -> Vec<Vec<(usize, usize)>> {
+    let inside = |x: isize, y: isize| {
+        (0..n_columns as isize).contains(&x) && (0..n_rows as isize).contains(&y)
+    };
+    let mut lines = Vec::new();
+    for y in 0..n_rows as isize {
+        for x in 0..n_columns as isize {
+            //Each line starts with the tree whose next step leaves the grid
+            if inside(x + dx, y + dy) {
+                continue;
+            }
+            let mut line = Vec::new();
+            let (mut line_x, mut line_y) = (x, y);
+            while inside(line_x, line_y) {
+                line.push((line_x as usize, line_y as usize));
+                line_x -= dx;
+                line_y -= dy;
+            }
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// The cells a ray from a tree goes through until it leaves the grid, the tree excluded.
+fn bresenham_ray(
+    n_rows: usize,
+    n_columns: usize,
+    x: usize,
+    y: usize,
+    angle: f64,
+) -> Vec<(usize, usize)> {
+    //Far enough for the ray to leave the grid whatever its angle
+    let length = (n_rows + n_columns) as f64;
+    let (x0, y0) = (x as isize, y as isize);
+    let x1 = x0 + (length * angle.cos()).round() as isize;
+    //Angles go counterclockwise, so up is towards smaller y
+    let y1 = y0 - (length * angle.sin()).round() as isize;
+
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+    let mut error = dx + dy;
+    let (mut cx, mut cy) = (x0, y0);
+    let mut cells = Vec::new();
+    while (cx, cy) != (x1, y1) {
+        let double_error = 2 * error;
+        if double_error >= dy {
+            error += dy;
+            cx += sx;
+        }
+        if double_error <= dx {
+            error += dx;
+            cy += sy;
+        }
+        if !(0..n_columns as isize).contains(&cx) || !(0..n_rows as isize).contains(&cy) {
+            break;
+        }
+        cells.push((cx as usize, cy as usize));
+    }
+    cells
+}
+
+fn ray_angles(n_rays: usize) -> Vec<f64> {
+    (0..n_rays)
+        .map(|i| i as f64 * std::f64::consts::TAU / n_rays as f64)
+        .collect()
+}
+
+impl Directions {
+    /// The steps of the directions, None for rays that can go at any angle.
+    pub fn steps(&self) -> Option<Vec<Step>> {
+        match self {
+            Directions::Cardinal => Some(CARDINAL.to_vec()),
+            Directions::Diagonal => Some(DIAGONAL.to_vec()),
+            Directions::All => Some(CARDINAL.iter().chain(&DIAGONAL).copied().collect()),
+            Directions::Rays(_) => None,
+        }
+    }
+}
+
+/// Whether each tree can be seen from outside the grid in one of the directions.
+///
+/// Along a step, a tree is visible from the edge when it is taller than every tree before it on
+/// the line from that edge, which one sweep per line finds. Rays are cast from every tree instead,
+/// each one followed to the edge of the grid, so this is no longer linear in the size of the grid.
+pub fn visibility(grid: &Grid, directions: &Directions) -> Vec<Vec<bool>> {
+    let (n_rows, n_columns) = grid_size(grid);
+    let mut visible = vec![vec![false; n_columns]; n_rows];
+    match directions.steps() {
+        Some(steps) => {
+            for step in steps {
+                for line in lines_towards(n_rows, n_columns, step) {
+                    let mut max_height = None;
+                    for (x, y) in line {
+                        let height = grid[y][x].height;
+                        if max_height.is_none_or(|max_height| height > max_height) {
+                            visible[y][x] = true;
+                            max_height = Some(height);
+                        }
+                    }
+                }
+            }
+        }
+        None => {
+            let Directions::Rays(n_rays) = directions else {
+                unreachable!("Only rays have no steps");
+            };
+            for (y, row) in visible.iter_mut().enumerate() {
+                for (x, visible) in row.iter_mut().enumerate() {
+                    let height = grid[y][x].height;
+                    *visible = ray_angles(*n_rays).into_iter().any(|angle| {
+                        bresenham_ray(n_rows, n_columns, x, y, angle)
+                            .iter()
+                            .all(|&(x, y)| grid[y][x].height < height)
+                    });
+                }
+            }
+        }
+    }
+    visible
+}
+
+/// How many trees each tree sees in each direction, up to the first one at least as tall.
+///
+/// Along a step, the stack holds the trees already seen that are not hidden behind a taller or
+/// equal one closer to the current tree, so their heights are decreasing. The trees shorter than
+/// the current one are popped: the current tree blocks the view of anything after it at least as
+/// well as them.
+pub fn viewing_distances(grid: &Grid, directions: &Directions) -> Vec<Vec<Vec<usize>>> {
+    let (n_rows, n_columns) = grid_size(grid);
+    let Some(steps) = directions.steps() else {
+        let Directions::Rays(n_rays) = directions else {
+            unreachable!("Only rays have no steps");
+        };
+        return (0..n_rows)
+            .map(|y| {
+                (0..n_columns)
+                    .map(|x| {
+                        let height = grid[y][x].height;
+                        ray_angles(*n_rays)
+                            .into_iter()
+                            .map(|angle| {
+                                let ray = bresenham_ray(n_rows, n_columns, x, y, angle);
+                                match ray.iter().position(|&(x, y)| grid[y][x].height >= height) {
+                                    Some(i) => i + 1,
+                                    None => ray.len(),
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+    };
+
+    let mut distances = vec![vec![vec![0; steps.len()]; n_columns]; n_rows];
+    for (direction, &step) in steps.iter().enumerate() {
+        for line in lines_towards(n_rows, n_columns, step) {
+            let mut stack: Vec<usize> = Vec::new();
+            for (i, &(x, y)) in line.iter().enumerate() {
+                let height = grid[y][x].height;
+                while stack
+                    .last()
+                    .is_some_and(|&j| grid[line[j].1][line[j].0].height < height)
+                {
+                    stack.pop();
+                }
+                //Without a blocking tree, the view goes to the edge
+                distances[y][x][direction] = i - stack.last().copied().unwrap_or(0);
+                stack.push(i);
+            }
+        }
+    }
+    distances
+}
+
+pub fn scenic_scores(grid: &Grid, options: &ViewOptions) -> Vec<Vec<usize>> {
+    viewing_distances(grid, &options.directions)
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .map(|distances| options.combination.combine(distances))
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_grid_from_string, random_input, TEST_INPUT};
+
+    /// Walks from the tree one step at a time, as many trees as it sees.
+    fn naive_distance(grid: &Grid, x: usize, y: usize, (dx, dy): Step) -> (bool, usize) {
+        let (n_rows, n_columns) = grid_size(grid);
+        let height = grid[y][x].height;
+        let (mut cx, mut cy) = (x as isize + dx, y as isize + dy);
+        let mut distance = 0;
+        while (0..n_columns as isize).contains(&cx) && (0..n_rows as isize).contains(&cy) {
+            distance += 1;
+            if grid[cy as usize][cx as usize].height >= height {
+                return (false, distance);
+            }
+            cx += dx;
+            cy += dy;
+        }
+        (true, distance)
+    }
+
+    #[test]
+    fn test_steps_match_naive() {
+        let mut seed = 46;
+        let mut inputs = vec![TEST_INPUT.to_string()];
+        for (n_rows, n_columns) in [(1, 1), (1, 6), (5, 1), (8, 11), (15, 9)] {
+            inputs.push(random_input(n_rows, n_columns, &mut seed));
+        }
+        for input in inputs {
+            let grid = build_grid_from_string(&input).unwrap();
+            for directions in [Directions::Cardinal, Directions::Diagonal, Directions::All] {
+                let steps = directions.steps().unwrap();
+                let visible = visibility(&grid, &directions);
+                let distances = viewing_distances(&grid, &directions);
+                for y in 0..grid.len() {
+                    for x in 0..grid[y].len() {
+                        let naive: Vec<_> = steps
+                            .iter()
+                            .map(|&step| naive_distance(&grid, x, y, step))
+                            .collect();
+                        assert_eq!(
+                            visible[y][x],
+                            naive.iter().any(|(visible, _)| *visible),
+                            "{:?} at ({}, {}) in\n{}",
+                            directions,
+                            x,
+                            y,
+                            input
+                        );
+                        let naive_distances: Vec<_> = naive.iter().map(|(_, d)| *d).collect();
+                        assert_eq!(distances[y][x], naive_distances);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rays_match_steps() {
+        //Rays at multiples of 45 degrees go along the steps
+        let mut seed = 8;
+        let grid = build_grid_from_string(&random_input(12, 10, &mut seed)).unwrap();
+        for (n_rays, directions) in [(4, Directions::Cardinal), (8, Directions::All)] {
+            assert_eq!(
+                visibility(&grid, &Directions::Rays(n_rays)),
+                visibility(&grid, &directions)
+            );
+            let ray_distances = viewing_distances(&grid, &Directions::Rays(n_rays));
+            let step_distances = viewing_distances(&grid, &directions);
+            for (ray_row, step_row) in ray_distances.iter().zip(&step_distances) {
+                for (ray, step) in ray_row.iter().zip(step_row) {
+                    let (mut ray, mut step) = (ray.clone(), step.clone());
+                    ray.sort();
+                    step.sort();
+                    assert_eq!(ray, step);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bresenham_ray() {
+        //A slope of one half goes one cell up every two cells right
+        let angle = 0.5_f64.atan();
+        assert_eq!(
+            bresenham_ray(5, 7, 0, 4, angle),
+            vec![(1, 4), (2, 3), (3, 3), (4, 2), (5, 2), (6, 1)]
+        );
+        assert!(bresenham_ray(5, 7, 6, 0, 0.0).is_empty());
+    }
+
+    #[test]
+    fn test_combinations() {
+        let grid = build_grid_from_string(TEST_INPUT).unwrap();
+        let best = |options: ViewOptions| {
+            scenic_scores(&grid, &options)
+                .into_iter()
+                .flatten()
+                .max()
+                .unwrap()
+        };
+        assert_eq!(best(ViewOptions::default()), 8);
+        let sum = ViewOptions {
+            combination: Combination::Sum,
+            ..Default::default()
+        };
+        assert_eq!(best(sum), 8);
+        let diagonal_product = ViewOptions {
+            directions: Directions::Diagonal,
+            ..Default::default()
+        };
+        assert_eq!(best(diagonal_product), 3);
+        assert_eq!(Combination::Min.combine(&[3, 1, 2]), 1);
+        assert_eq!(Combination::Max.combine(&[3, 1, 2]), 3);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::sight::{self, ViewOptions};
+use crate::{grid_size, Grid};
+
+//How many branches the exact selection explores before settling for the best selection so far
+pub const MAX_NODES: usize = 1 << 20;
+
+/// A candidate tree for a tree house, with its viewing distances in the order of the directions.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Site {
+    pub x: usize,
+    pub y: usize,
+    pub height: usize,
+    pub score: usize,
+    pub distances: Vec<usize>,
+}
+
+/// Which trees can be sites. Spacing is counted in moves of a chess king, so a spacing of 2 keeps
+/// sites from touching, even diagonally.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct SiteFilter {
+    pub min_height: usize,
+    pub exclude_edges: bool,
+    pub min_spacing: usize,
+}
+
+fn spacing(a: &Site, b: &Site) -> usize {
+    a.x.abs_diff(b.x).max(a.y.abs_diff(b.y))
+}
+
+/// The trees passing the filter's height and edge conditions, best score first, then in reading
+/// order.
+fn candidates(grid: &Grid, options: &ViewOptions, filter: &SiteFilter) -> Vec<Site> {
+    let (n_rows, n_columns) = grid_size(grid);
+    let distances = sight::viewing_distances(grid, &options.directions);
+    let mut sites = Vec::new();
+    for (y, row) in distances.into_iter().enumerate() {
+        for (x, distances) in row.into_iter().enumerate() {
+            let height = grid[y][x].height;
+            let on_edge = x == 0 || y == 0 || x + 1 == n_columns || y + 1 == n_rows;
+            if height < filter.min_height || (filter.exclude_edges && on_edge) {
+                continue;
+            }
+            sites.push(Site {
+                x,
+                y,
+                height,
+                score: options.combination.combine(&distances),
+                distances,
+            });
+        }
+    }
+    sites.sort_by(|a, b| b.score.cmp(&a.score).then((a.y, a.x).cmp(&(b.y, b.x))));
+    sites
+}
+
+/// The best `k` sites by score, each one at least the minimum spacing away from the better ones
+/// already chosen. There are fewer when not enough trees pass the filter.
+pub fn top_k(grid: &Grid, options: &ViewOptions, filter: &SiteFilter, k: usize) -> Vec<Site> {
+    let mut chosen: Vec<Site> = Vec::new();
+    for site in candidates(grid, options, filter) {
+        if chosen.len() == k {
+            break;
+        }
+        if chosen
+            .iter()
+            .all(|other| spacing(&site, other) >= filter.min_spacing)
+        {
+            chosen.push(site);
+        }
+    }
+    chosen
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Selection {
+    pub sites: Vec<Site>,
+    pub total: usize,
+    //False when the search ran out of nodes, the selection may then not be the best one
+    pub exact: bool,
+}
+
+struct Search<'a> {
+    candidates: &'a [Site],
+    k: usize,
+    min_spacing: usize,
+    chosen: Vec<usize>,
+    best: Option<(usize, Vec<usize>)>,
+    nodes: usize,
+    max_nodes: usize,
+}
+
+impl Search<'_> {
+    fn explore(&mut self, from: usize, total: usize) {
+        if self.chosen.len() == self.k {
+            if self.best.as_ref().is_none_or(|(best, _)| total > *best) {
+                self.best = Some((total, self.chosen.clone()));
+            }
+            return;
+        }
+        for i in from..self.candidates.len() {
+            if self.nodes >= self.max_nodes {
+                return;
+            }
+            self.nodes += 1;
+            //Candidates are sorted, so the next ones bound what the rest of the selection can add
+            let missing = self.k - self.chosen.len();
+            if i + missing > self.candidates.len() {
+                return;
+            }
+            let bound: usize = self.candidates[i..i + missing]
+                .iter()
+                .map(|site| site.score)
+                .sum();
+            if self
+                .best
+                .as_ref()
+                .is_some_and(|(best, _)| total + bound <= *best)
+            {
+                return;
+            }
+            let site = &self.candidates[i];
+            if self
+                .chosen
+                .iter()
+                .all(|&j| spacing(site, &self.candidates[j]) >= self.min_spacing)
+            {
+                self.chosen.push(i);
+                self.explore(i + 1, total + site.score);
+                self.chosen.pop();
+            }
+        }
+    }
+}
+
+/// The `k` sites with the largest total score such that no two of them are adjacent, even
+/// diagonally, nor closer than the minimum spacing. None when `k` such sites do not exist, or
+/// when none were found within `max_nodes` branches.
+///
+/// This is a branch and bound over the candidates by decreasing score, starting from the greedy
+/// selection of `top_k`. Choosing spaced sites is hard in general, so the search gives up after
+/// `max_nodes` branches and reports the best selection found as not exact.
+pub fn select(
+    grid: &Grid,
+    options: &ViewOptions,
+    filter: &SiteFilter,
+    k: usize,
+    max_nodes: usize,
+) -> Option<Selection> {
+    let candidates = candidates(grid, options, filter);
+    let min_spacing = filter.min_spacing.max(2);
+
+    let mut search = Search {
+        candidates: &candidates,
+        k,
+        min_spacing,
+        chosen: Vec::new(),
+        best: None,
+        nodes: 0,
+        max_nodes,
+    };
+    let greedy = top_k(
+        grid,
+        options,
+        &SiteFilter {
+            min_spacing,
+            ..*filter
+        },
+        k,
+    );
+    if greedy.len() == k {
+        let indices = greedy
+            .iter()
+            .map(|site| candidates.iter().position(|other| other == site).unwrap())
+            .collect();
+        search.best = Some((greedy.iter().map(|site| site.score).sum(), indices));
+    }
+    search.explore(0, 0);
+
+    let exact = search.nodes < max_nodes;
+    search.best.map(|(total, indices)| Selection {
+        sites: indices.into_iter().map(|i| candidates[i].clone()).collect(),
+        total,
+        exact,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_grid_from_string, random_input, TEST_INPUT};
+
+    fn positions(sites: &[Site]) -> Vec<(usize, usize)> {
+        sites.iter().map(|site| (site.x, site.y)).collect()
+    }
+
+    #[test]
+    fn test_top_k() {
+        let grid = build_grid_from_string(TEST_INPUT).unwrap();
+        let options = ViewOptions::default();
+        let top = top_k(&grid, &options, &SiteFilter::default(), 3);
+        assert_eq!(
+            top[0],
+            Site {
+                x: 2,
+                y: 3,
+                height: 5,
+                score: 8,
+                distances: vec![2, 2, 2, 1],
+            }
+        );
+        assert_eq!(positions(&top), vec![(2, 3), (1, 2), (2, 1)]);
+
+        let tall = SiteFilter {
+            min_height: 6,
+            ..Default::default()
+        };
+        assert_eq!(
+            positions(&top_k(&grid, &options, &tall, 10)),
+            vec![(3, 0), (0, 2), (4, 3), (3, 4)]
+        );
+        //Only the nine inner trees are left
+        let inner = SiteFilter {
+            exclude_edges: true,
+            ..Default::default()
+        };
+        assert_eq!(top_k(&grid, &options, &inner, 20).len(), 9);
+        let spaced = SiteFilter {
+            min_spacing: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            positions(&top_k(&grid, &options, &spaced, 2)),
+            vec![(2, 3), (2, 1)]
+        );
+    }
+
+    fn brute_force(sites: &[Site], k: usize, min_spacing: usize) -> Option<usize> {
+        if k == 0 {
+            return Some(0);
+        }
+        let (first, rest) = sites.split_first()?;
+        let with: Vec<Site> = rest
+            .iter()
+            .filter(|other| spacing(first, other) >= min_spacing)
+            .cloned()
+            .collect();
+        let with = brute_force(&with, k - 1, min_spacing).map(|total| total + first.score);
+        let without = brute_force(rest, k, min_spacing);
+        with.max(without)
+    }
+
+    #[test]
+    fn test_select_matches_brute_force() {
+        let mut seed: u64 = 47;
+        for (n_rows, n_columns, k) in [(5, 5, 3), (6, 4, 4), (7, 7, 5), (3, 8, 4), (2, 2, 2)] {
+            let input = random_input(n_rows, n_columns, &mut seed);
+            let grid = build_grid_from_string(&input).unwrap();
+            let options = ViewOptions::default();
+            let filter = SiteFilter::default();
+            let expected = brute_force(&candidates(&grid, &options, &filter), k, 2);
+            let selection = select(&grid, &options, &filter, k, MAX_NODES);
+            assert_eq!(
+                selection.as_ref().map(|selection| selection.total),
+                expected,
+                "{}",
+                input
+            );
+            if let Some(selection) = selection {
+                assert!(selection.exact);
+                assert_eq!(
+                    selection.sites.iter().map(|site| site.score).sum::<usize>(),
+                    selection.total
+                );
+                for (i, a) in selection.sites.iter().enumerate() {
+                    for b in &selection.sites[i + 1..] {
+                        assert!(spacing(a, b) >= 2);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_select_out_of_nodes() {
+        let grid = build_grid_from_string(TEST_INPUT).unwrap();
+        let selection = select(&grid, &ViewOptions::default(), &SiteFilter::default(), 4, 1);
+        assert!(!selection.unwrap().exact);
+    }
+}
@@ -1,7 +1,7 @@
-use itertools::Itertools;
-use std::cell::RefCell;
 use std::collections::HashSet;
 
+mod render;
+
 #[derive(Debug, Clone, Copy)]
 enum Direction {
     U,
@@ -73,88 +73,44 @@ impl Position {
     }
 }
 
-struct Rope {
-    head: Position,
-    tail: Position,
+/// A rope of `N` knots, the head first. Each knot remembers every position it has been at.
+struct Rope<const N: usize> {
+    knots: [Position; N],
+    visited: [HashSet<Position>; N],
 }
 
-impl Rope {
+impl<const N: usize> Rope<N> {
     fn new(pos: Position) -> Self {
+        const { assert!(N > 0, "A rope needs at least a head") };
         Rope {
-            head: pos,
-            tail: pos,
+            knots: [pos; N],
+            visited: std::array::from_fn(|_| HashSet::from([pos])),
         }
     }
 
-    fn move_head(&mut self, dir: Direction) {
-        self.head.translate(dir);
-        Position::run_planck_physics(self.head, &mut self.tail);
-    }
-
-    #[allow(dead_code)]
-    pub fn print_on_grid(&self, n_columns: u32, n_rows: u32) {
-        let mut lines: Vec<Vec<char>> = (0..n_rows)
-            .map(|_| (0..n_columns).map(|_| '.').collect())
-            .collect();
-
-        let tail = self.tail;
-        let head = self.head;
-        lines[n_rows as usize - 1 - tail.y as usize][tail.x as usize] = 'T';
-        lines[n_rows as usize - 1 - head.y as usize][head.x as usize] = 'H';
-        if tail == head {
-            lines[n_rows as usize - 1 - tail.y as usize][tail.x as usize] = 'B';
-        }
-        let grid = (lines
-            .iter()
-            .map(|line| line.iter().collect())
-            .collect::<Vec<String>>())
-        .join("\n");
-
-        println!("{grid}")
-    }
-}
-
-struct LongRope {
-    knots: Vec<RefCell<Position>>,
-}
-
-impl LongRope {
-    fn new(pos: Position, len: u32) -> Self {
-        LongRope {
-            knots: (0..len).map(|_| RefCell::new(pos)).collect(),
-        }
+    /// The positions the knot has been at, the start included.
+    fn visited(&self, knot: usize) -> &HashSet<Position> {
+        &self.visited[knot]
     }
 
     fn move_head(&mut self, dir: Direction) {
-        self.knots[0].borrow_mut().translate(dir);
+        self.knots[0].translate(dir);
+        self.visited[0].insert(self.knots[0]);
 
-        for (head, tail) in self.knots.iter().tuple_windows() {
-            Position::run_planck_physics(*head.borrow(), &mut tail.borrow_mut())
+        for i in 1..N {
+            let head = self.knots[i - 1];
+            Position::run_planck_physics(head, &mut self.knots[i]);
+            self.visited[i].insert(self.knots[i]);
         }
     }
 
-    #[allow(dead_code)]
-    fn print_on_grid(&self, n_columns: u32, n_rows: u32) {
-        let mut lines: Vec<Vec<char>> = (0..n_rows)
-            .map(|_| (0..n_columns).map(|_| '.').collect())
-            .collect();
-
-        for (i, knot) in self.knots.iter().enumerate().rev() {
-            lines[n_rows as usize - 1 - knot.borrow().y as usize][knot.borrow().x as usize] =
-                if i == 0 {
-                    'H'
-                } else {
-                    char::from_digit(i as u32, 10).unwrap()
-                };
+    /// `H` for the head, then `T` for the tail of a two knot rope or the number of the knot.
+    fn label(knot: usize) -> char {
+        match knot {
+            0 => 'H',
+            1 if N == 2 => 'T',
+            _ => char::from_digit(knot as u32, 36).unwrap_or('#'),
         }
-
-        let grid = (lines
-            .iter()
-            .map(|line| line.iter().collect())
-            .collect::<Vec<String>>())
-        .join("\n");
-
-        println!("{grid}")
     }
 }
 
@@ -191,50 +147,119 @@ fn parse_moves(input: &str) -> Vec<Action> {
     input.lines().map(parse_line).collect()
 }
 
-fn get_answer_a(input: &str) -> i32 {
+fn simulate<const N: usize>(input: &str) -> Rope<N> {
     let mut rope = Rope::new(Position { x: 0, y: 0 });
-    let actions = parse_moves(input);
-    let mut visited_by_tail = HashSet::new();
-
-    for action in actions {
-        //println!("Action: {:?}", action);
+    for action in parse_moves(input) {
         for _ in 0..action.units {
             rope.move_head(action.dir);
-            visited_by_tail.insert(rope.tail);
-            //rope.print_on_grid(6, 5);
-            //println!()
         }
     }
+    rope
+}
 
-    visited_by_tail.len() as i32
+fn get_answer_a(input: &str) -> i32 {
+    simulate::<2>(input).visited(1).len() as i32
 }
 
 fn get_answer_b(input: &str) -> i32 {
-    let mut long_rope = LongRope::new(Position { x: 11, y: 5 }, 10);
-    let actions = parse_moves(input);
-    let mut visited_by_tail = HashSet::new();
+    simulate::<10>(input).visited(9).len() as i32
+}
 
-    for action in actions {
-        //println!("Action: {:?}", action);
-        for _ in 0..action.units {
-            long_rope.move_head(action.dir);
-            visited_by_tail.insert(*long_rope.knots.last().unwrap().borrow());
+fn main() {
+    let mut print = false;
+    let mut n_knots = 10;
+    let mut every = 1;
+    let mut gif_path = None;
+    let mut cast_path = None;
+    let mut frames_path = None;
+    let mut scale = 4;
+    //In hundredths of a second
+    let mut delay: u16 = 10;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--print" => print = true,
+            "--knots" => {
+                n_knots = args
+                    .next()
+                    .and_then(|n_knots| n_knots.parse().ok())
+                    .expect("Expecting 2 or 10 after --knots")
+            }
+            "--every" => {
+                every = args
+                    .next()
+                    .and_then(|every| every.parse().ok())
+                    .expect("Expecting a number of steps after --every")
+            }
+            "--gif" => gif_path = Some(args.next().expect("Expecting a file after --gif")),
+            "--cast" => cast_path = Some(args.next().expect("Expecting a file after --cast")),
+            "--frames" => frames_path = Some(args.next().expect("Expecting a file after --frames")),
+            "--scale" => {
+                scale = args
+                    .next()
+                    .and_then(|scale| scale.parse().ok())
+                    .expect("Expecting a number of pixels after --scale")
+            }
+            "--delay" => {
+                delay = args
+                    .next()
+                    .and_then(|delay| delay.parse().ok())
+                    .expect("Expecting hundredths of a second after --delay")
+            }
+            arg => panic!("Unknown argument: {}", arg),
         }
-        //long_rope.print_on_grid(30, 30);
-        //println!()
     }
 
-    visited_by_tail.len() as i32
-}
-
-fn main() {
     let input_path = "input/input.txt";
     let input = std::fs::read_to_string(input_path).unwrap();
 
+    if print {
+        match n_knots {
+            2 => println!("{}\n", render::rope_to_text(&simulate::<2>(&input))),
+            10 => println!("{}\n", render::rope_to_text(&simulate::<10>(&input))),
+            _ => panic!("Expecting 2 or 10 knots"),
+        }
+    }
+    if gif_path.is_some() || cast_path.is_some() || frames_path.is_some() {
+        let actions = parse_moves(&input);
+        let recording = match n_knots {
+            2 => render::record::<2>(&actions, every),
+            10 => render::record::<10>(&actions, every),
+            _ => panic!("Expecting 2 or 10 knots"),
+        };
+        if let Some(path) = gif_path {
+            match render::to_gif(&recording, scale, delay) {
+                Ok(gif) => std::fs::write(path, gif).expect("Expecting a writable file"),
+                Err(error) => {
+                    eprintln!("Can not write {}: {:?}", path, error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if let Some(path) = cast_path {
+            std::fs::write(path, render::to_cast(&recording, delay as f64 / 100.0))
+                .expect("Expecting a writable file");
+        }
+        if let Some(path) = frames_path {
+            std::fs::write(path, render::to_text_frames(&recording))
+                .expect("Expecting a writable file");
+        }
+    }
+
     println!("Answer A: {}", get_answer_a(&input));
     println!("Answer B: {}", get_answer_b(&input));
 }
 
+/// The next number of a linear congruential generator, for pseudo random test data.
+#[cfg(test)]
+fn next_random(seed: &mut u64) -> u64 {
+    *seed = seed
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407);
+    *seed >> 33
+}
+
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
@@ -264,14 +289,31 @@ mod tests {
                                 D 10\n\
                                 L 25\n\
                                 U 20\n";
-        //let test_input: &str = "R 4\n\
-        //                        U 4\n\
-        //                        L 3\n\
-        //                        D 1\n\
-        //                        R 4\n\
-        //                        D 1\n\
-        //                        L 5\n\
-        //                        R 2\n";
         assert_eq!(get_answer_b(test_input), 36);
     }
+
+    #[test]
+    fn test_visited_per_knot() {
+        let test_input: &str = "R 4\n\
+                                U 4\n\
+                                L 3\n\
+                                D 1\n\
+                                R 4\n\
+                                D 1\n\
+                                L 5\n\
+                                R 2\n";
+        let short = simulate::<2>(test_input);
+        let long = simulate::<10>(test_input);
+        assert_eq!(short.knots[0], Position { x: 2, y: 2 });
+        assert_eq!(short.knots[1], Position { x: 1, y: 2 });
+        //The knot after the head moves the same whatever follows it
+        assert_eq!(short.visited(0), long.visited(0));
+        assert_eq!(short.visited(1), long.visited(1));
+        //The tail of the long rope never leaves the start, the knots before it do
+        assert_eq!(long.visited(9), &HashSet::from([Position { x: 0, y: 0 }]));
+        assert_eq!(
+            (0..10).map(|i| long.visited(i).len()).collect::<Vec<_>>(),
+            vec![21, 13, 7, 4, 3, 2, 1, 1, 1, 1]
+        );
+    }
 }
@@ -0,0 +1,605 @@
+use std::fmt::Write;
+
+use crate::{Action, Position, Rope};
+
+/// The smallest rectangle holding a set of positions. y grows upwards, so the top row is `max_y`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Bounds {
+    pub min_x: i32,
+    pub max_x: i32,
+    pub min_y: i32,
+    pub max_y: i32,
+}
+
+impl Bounds {
+    pub fn around(positions: impl IntoIterator<Item = Position>) -> Option<Self> {
+        positions.into_iter().fold(None, |bounds, p| {
+            Some(match bounds {
+                None => Bounds {
+                    min_x: p.x,
+                    max_x: p.x,
+                    min_y: p.y,
+                    max_y: p.y,
+                },
+                Some(Bounds {
+                    min_x,
+                    max_x,
+                    min_y,
+                    max_y,
+                }) => Bounds {
+                    min_x: min_x.min(p.x),
+                    max_x: max_x.max(p.x),
+                    min_y: min_y.min(p.y),
+                    max_y: max_y.max(p.y),
+                },
+            })
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max_x - self.min_x) as usize + 1
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max_y - self.min_y) as usize + 1
+    }
+
+    //Cells go row by row from the top left corner
+    fn index(&self, p: Position) -> usize {
+        (self.max_y - p.y) as usize * self.width() + (p.x - self.min_x) as usize
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Cell {
+    Empty,
+    //Visited by the tail
+    Trail,
+    Start,
+    Knot(usize),
+}
+
+/// Draws the knots over the start, and the start over the trail.
+fn draw(bounds: &Bounds, trail: &[bool], start: Option<Position>, knots: &[Position]) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = trail
+        .iter()
+        .map(|&visited| if visited { Cell::Trail } else { Cell::Empty })
+        .collect();
+    if let Some(start) = start {
+        cells[bounds.index(start)] = Cell::Start;
+    }
+    //Knots closer to the head are drawn over the ones behind them
+    for (i, &knot) in knots.iter().enumerate().rev() {
+        cells[bounds.index(knot)] = Cell::Knot(i);
+    }
+    cells
+}
+
+fn to_text(cells: &[Cell], bounds: &Bounds, labels: &[char]) -> Vec<String> {
+    cells
+        .chunks(bounds.width())
+        .map(|row| {
+            row.iter()
+                .map(|cell| match cell {
+                    Cell::Empty => '.',
+                    Cell::Trail => '#',
+                    Cell::Start => 's',
+                    Cell::Knot(i) => labels[*i],
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The rope as the puzzle draws it, just large enough for its knots and the trail of its tail.
+pub fn rope_to_text<const N: usize>(rope: &Rope<N>) -> String {
+    let trail = rope.visited(N - 1);
+    let bounds = Bounds::around(rope.knots.iter().chain(trail).copied())
+        .expect("A rope has at least a head");
+    let mut visited = vec![false; bounds.width() * bounds.height()];
+    for &p in trail {
+        visited[bounds.index(p)] = true;
+    }
+    let cells = draw(&bounds, &visited, None, &rope.knots);
+    let labels: Vec<char> = (0..N).map(Rope::<N>::label).collect();
+    to_text(&cells, &bounds, &labels).join("\n")
+}
+
+#[derive(Debug)]
+pub struct Frame {
+    pub step: usize,
+    pub knots: Vec<Position>,
+    //Where the tail went since the previous frame
+    pub tail_moves: Vec<Position>,
+}
+
+/// The states of a rope along its moves, from the start.
+#[derive(Debug)]
+pub struct Recording {
+    pub frames: Vec<Frame>,
+    pub labels: Vec<char>,
+}
+
+/// Moves a rope of `N` knots from the origin, keeping one frame every `every` steps. The last
+/// state is always kept.
+pub fn record<const N: usize>(actions: &[Action], every: usize) -> Recording {
+    let every = every.max(1);
+    let mut rope = Rope::<N>::new(Position { x: 0, y: 0 });
+    let mut frames = vec![Frame {
+        step: 0,
+        knots: rope.knots.to_vec(),
+        tail_moves: vec![rope.knots[N - 1]],
+    }];
+    let mut tail_moves = vec![];
+    let mut step = 0;
+    for action in actions {
+        for _ in 0..action.units {
+            rope.move_head(action.dir);
+            step += 1;
+            tail_moves.push(rope.knots[N - 1]);
+            if step % every == 0 {
+                frames.push(Frame {
+                    step,
+                    knots: rope.knots.to_vec(),
+                    tail_moves: std::mem::take(&mut tail_moves),
+                });
+            }
+        }
+    }
+    if !tail_moves.is_empty() {
+        frames.push(Frame {
+            step,
+            knots: rope.knots.to_vec(),
+            tail_moves,
+        });
+    }
+    Recording {
+        frames,
+        labels: (0..N).map(Rope::<N>::label).collect(),
+    }
+}
+
+impl Recording {
+    /// Large enough for every frame, so the view stays still during the animation.
+    pub fn bounds(&self) -> Bounds {
+        Bounds::around(
+            self.frames
+                .iter()
+                .flat_map(|frame| frame.knots.iter().chain(&frame.tail_moves))
+                .copied(),
+        )
+        .expect("A recording has at least the starting frame")
+    }
+
+    /// The cells of each frame, with the trail of the tail so far.
+    fn cells(&self, bounds: Bounds) -> impl Iterator<Item = Vec<Cell>> + '_ {
+        let start = self.frames[0].knots[0];
+        let mut trail = vec![false; bounds.width() * bounds.height()];
+        self.frames.iter().map(move |frame| {
+            for &p in &frame.tail_moves {
+                trail[bounds.index(p)] = true;
+            }
+            draw(&bounds, &trail, Some(start), &frame.knots)
+        })
+    }
+}
+
+/// Every frame as text under a header with its step, separated by blank lines.
+pub fn to_text_frames(recording: &Recording) -> String {
+    let bounds = recording.bounds();
+    let mut text = String::new();
+    for (frame, cells) in recording.frames.iter().zip(recording.cells(bounds)) {
+        writeln!(text, "== Step {} ==", frame.step).unwrap();
+        for line in to_text(&cells, &bounds, &recording.labels) {
+            writeln!(text, "{}", line).unwrap();
+        }
+        text += "\n";
+    }
+    text
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for char in text.chars() {
+        match char {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            '\r' => json += "\\r",
+            '\n' => json += "\\n",
+            char if (char as u32) < 0x20 => write!(json, "\\u{:04x}", char as u32).unwrap(),
+            char => json.push(char),
+        }
+    }
+    json + "\""
+}
+
+/// An asciinema cast (version 2) showing a frame every `delay` seconds, each one drawn over the
+/// previous one from the top left corner of the terminal.
+pub fn to_cast(recording: &Recording, delay: f64) -> String {
+    let bounds = recording.bounds();
+    let mut cast = format!(
+        "{{\"version\": 2, \"width\": {}, \"height\": {}}}\n",
+        bounds.width().max(20),
+        bounds.height() + 1
+    );
+    for (i, (frame, cells)) in recording
+        .frames
+        .iter()
+        .zip(recording.cells(bounds))
+        .enumerate()
+    {
+        let mut screen = format!("\x1b[H\x1b[2JStep {}", frame.step);
+        for line in to_text(&cells, &bounds, &recording.labels) {
+            screen += "\r\n";
+            screen += &line;
+        }
+        writeln!(
+            cast,
+            "[{:.3}, \"o\", {}]",
+            i as f64 * delay,
+            json_string(&screen)
+        )
+        .unwrap();
+    }
+    cast
+}
+
+const PALETTE: [[u8; 3]; 8] = [
+    //Empty
+    [250, 250, 250],
+    //Trail
+    [160, 200, 240],
+    //Start
+    [40, 160, 60],
+    //Head
+    [220, 40, 40],
+    //Knots in between
+    [90, 90, 90],
+    //Tail
+    [30, 60, 160],
+    [0, 0, 0],
+    [0, 0, 0],
+];
+//Enough bits for the indices in the palette
+const MIN_CODE_SIZE: u8 = 3;
+const MAX_CODE: u16 = 4095;
+
+fn color_index(cell: Cell, n_knots: usize) -> u8 {
+    match cell {
+        Cell::Empty => 0,
+        Cell::Trail => 1,
+        Cell::Start => 2,
+        Cell::Knot(0) => 3,
+        Cell::Knot(i) if i + 1 == n_knots => 5,
+        Cell::Knot(_) => 4,
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    n_bits: u8,
+}
+
+impl BitWriter {
+    //GIF packs codes from the least significant bit
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.n_bits;
+        self.n_bits += size;
+        while self.n_bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.n_bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.n_bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/// The variable length LZW compression of GIF image data, before it is cut into sub-blocks.
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut writer = BitWriter {
+        bytes: vec![],
+        buffer: 0,
+        n_bits: 0,
+    };
+    //The code of each string followed by each index. 0 is a single index, so it marks a missing one
+    let mut codes = vec![0u16; (MAX_CODE as usize + 1) * PALETTE.len()];
+    let mut next = end + 1;
+    let mut size = MIN_CODE_SIZE + 1;
+
+    writer.write(clear, size);
+    let mut prefix: Option<u16> = None;
+    for &index in indices {
+        let Some(code) = prefix else {
+            prefix = Some(index as u16);
+            continue;
+        };
+        let longer = &mut codes[code as usize * PALETTE.len() + index as usize];
+        if *longer != 0 {
+            prefix = Some(*longer);
+            continue;
+        }
+        if next <= MAX_CODE {
+            *longer = next;
+            writer.write(code, size);
+            //The decoder reads the next code with a bit more once this one is taken
+            if next == 1 << size && size < 12 {
+                size += 1;
+            }
+            next += 1;
+        } else {
+            writer.write(code, size);
+            writer.write(clear, size);
+            codes.fill(0);
+            next = end + 1;
+            size = MIN_CODE_SIZE + 1;
+        }
+        prefix = Some(index as u16);
+    }
+    if let Some(code) = prefix {
+        writer.write(code, size);
+    }
+    writer.write(end, size);
+    writer.finish()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GifError {
+    //GIF sizes are 16 bits, the size is in cells times the scale
+    TooBig { width: usize, height: usize },
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend(value.to_le_bytes());
+}
+
+/// An animated GIF with `scale` pixels per cell, showing each frame for `delay` hundredths of a
+/// second and looping forever. Fails when the image would be over 65535 pixels wide or high.
+pub fn to_gif(recording: &Recording, scale: usize, delay: u16) -> Result<Vec<u8>, GifError> {
+    let scale = scale.max(1);
+    let bounds = recording.bounds();
+    let (width, height) = (
+        bounds.width().saturating_mul(scale),
+        bounds.height().saturating_mul(scale),
+    );
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(GifError::TooBig { width, height });
+    };
+
+    let mut gif = b"GIF89a".to_vec();
+    push_u16(&mut gif, width);
+    push_u16(&mut gif, height);
+    //A global color table of 2^(2 + 1) colors, background from its first color
+    gif.extend([0xf2, 0, 0]);
+    gif.extend(PALETTE.iter().flatten());
+    //Loop forever
+    gif.extend([0x21, 0xff, 0x0b]);
+    gif.extend(b"NETSCAPE2.0");
+    gif.extend([0x03, 0x01, 0x00, 0x00, 0x00]);
+
+    let n_knots = recording.labels.len();
+    for cells in recording.cells(bounds) {
+        gif.extend([0x21, 0xf9, 0x04, 0x00]);
+        gif.extend(delay.to_le_bytes());
+        gif.extend([0x00, 0x00]);
+
+        gif.push(0x2c);
+        push_u16(&mut gif, 0);
+        push_u16(&mut gif, 0);
+        push_u16(&mut gif, width);
+        push_u16(&mut gif, height);
+        gif.push(0x00);
+
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        for row in cells.chunks(bounds.width()) {
+            let row: Vec<u8> = row
+                .iter()
+                .flat_map(|&cell| std::iter::repeat_n(color_index(cell, n_knots), scale))
+                .collect();
+            for _ in 0..scale {
+                pixels.extend(&row);
+            }
+        }
+        gif.push(MIN_CODE_SIZE);
+        for block in lzw(&pixels).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend(block);
+        }
+        gif.push(0x00);
+    }
+    gif.push(0x3b);
+    Ok(gif)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{next_random, parse_moves};
+
+    const TEST_INPUT: &str = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2\n";
+
+    #[test]
+    fn test_rope_to_text() {
+        let mut rope = Rope::<10>::new(Position { x: -3, y: -2 });
+        for action in parse_moves("L 2\nD 3\nR 1") {
+            for _ in 0..action.units {
+                rope.move_head(action.dir);
+            }
+        }
+        assert_eq!(
+            rope_to_text(&rope),
+            "..3\n\
+             .2.\n\
+             1..\n\
+             .H.",
+        );
+        let mut rope = Rope::<2>::new(Position { x: -1, y: 0 });
+        for action in parse_moves("R 3") {
+            for _ in 0..action.units {
+                rope.move_head(action.dir);
+            }
+        }
+        assert_eq!(rope_to_text(&rope), "##TH");
+    }
+
+    #[test]
+    fn test_to_text_frames() {
+        let recording = record::<2>(&parse_moves(TEST_INPUT), 4);
+        assert_eq!(
+            recording.bounds(),
+            Bounds {
+                min_x: 0,
+                max_x: 5,
+                min_y: 0,
+                max_y: 4,
+            }
+        );
+        let text = to_text_frames(&recording);
+        let frames: Vec<&str> = text.split("\n\n").collect();
+        //24 steps, a frame every 4 of them and the start
+        assert_eq!(frames.len(), 8);
+        assert_eq!(
+            frames[0],
+            "== Step 0 ==\n......\n......\n......\n......\nH....."
+        );
+        //The trail matches the puzzle's, with the start and the final rope over it
+        assert_eq!(
+            frames[6],
+            "== Step 24 ==\n..##..\n...##.\n.TH##.\n....#.\ns###.."
+        );
+    }
+
+    #[test]
+    fn test_to_cast() {
+        let recording = record::<10>(&parse_moves("R 2\nU 1"), 1);
+        let cast = to_cast(&recording, 0.5);
+        let lines: Vec<&str> = cast.lines().collect();
+        assert_eq!(lines[0], "{\"version\": 2, \"width\": 20, \"height\": 3}");
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[4],
+            "[1.500, \"o\", \"\\u001b[H\\u001b[2JStep 3\\r\\n..H\\r\\n21.\"]"
+        );
+    }
+
+    /// Decodes GIF image data, going through the codes as the format describes.
+    fn unlzw(bytes: &[u8]) -> Vec<u8> {
+        let clear = 1usize << MIN_CODE_SIZE;
+        let end = clear + 1;
+        let reset = || -> Vec<Vec<u8>> { (0..=end).map(|i| vec![i as u8]).collect() };
+        let mut table = reset();
+        let mut size = MIN_CODE_SIZE as usize + 1;
+        let mut previous: Option<usize> = None;
+        let mut output = vec![];
+        let mut bit = 0;
+        loop {
+            let mut code = 0;
+            for i in 0..size {
+                let position = bit + i;
+                code |= ((bytes[position / 8] >> (position % 8)) as usize & 1) << i;
+            }
+            bit += size;
+            if code == clear {
+                table = reset();
+                size = MIN_CODE_SIZE as usize + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                return output;
+            }
+            let entry = match (table.get(code), previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => {
+                    let mut entry = table[previous].clone();
+                    entry.push(table[previous][0]);
+                    entry
+                }
+                (None, None) => panic!("Unknown first code {}", code),
+            };
+            if let Some(previous) = previous {
+                if table.len() < 4096 {
+                    let mut added = table[previous].clone();
+                    added.push(entry[0]);
+                    table.push(added);
+                    if table.len() == 1 << size && size < 12 {
+                        size += 1;
+                    }
+                }
+            }
+            output.extend(&entry);
+            previous = Some(code);
+        }
+    }
+
+    #[test]
+    fn test_lzw() {
+        let mut seed: u64 = 50;
+        let noise: Vec<u8> = (0..20000)
+            .map(|_| (next_random(&mut seed) % 6) as u8)
+            .collect();
+        //Long runs fill the table slowly, noise fills it and clears it many times
+        for indices in [vec![], vec![3], vec![0; 10000], noise] {
+            assert_eq!(unlzw(&lzw(&indices)), indices);
+        }
+    }
+
+    #[test]
+    fn test_to_gif() {
+        let recording = record::<2>(&parse_moves(TEST_INPUT), 1);
+        let gif = to_gif(&recording, 3, 10).unwrap();
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(&gif[6..10], &[18, 0, 15, 0]);
+        assert_eq!(gif.last(), Some(&0x3b));
+
+        //Skips the header, the color table and the loop extension to the first frame
+        let mut i = 13 + 8 * 3 + 19;
+        let mut frames = vec![];
+        while gif[i] == 0x21 {
+            assert_eq!(&gif[i..i + 6], &[0x21, 0xf9, 0x04, 0x00, 10, 0]);
+            i += 8;
+            assert_eq!(gif[i], 0x2c);
+            i += 10;
+            assert_eq!(gif[i], MIN_CODE_SIZE);
+            i += 1;
+            let mut data = vec![];
+            while gif[i] != 0 {
+                data.extend(&gif[i + 1..i + 1 + gif[i] as usize]);
+                i += 1 + gif[i] as usize;
+            }
+            i += 1;
+            frames.push(unlzw(&data));
+        }
+        assert_eq!(gif[i], 0x3b);
+        assert_eq!(frames.len(), 25);
+        //The head covers the start in the first frame, then the tail does
+        let pixel = |frame: &[u8], x: usize, y: usize| frame[y * 18 + x];
+        assert_eq!(pixel(&frames[0], 1, 13), 3);
+        assert_eq!(pixel(&frames[1], 1, 13), 5);
+        assert_eq!(pixel(&frames[1], 4, 13), 3);
+        //At the end, the start shows again, and the trail went up on the right
+        assert_eq!(pixel(&frames[24], 0, 12), 2);
+        assert_eq!(pixel(&frames[24], 13, 7), 1);
+        assert_eq!(pixel(&frames[24], 13, 1), 0);
+
+        assert_eq!(
+            to_gif(&recording, 11000, 10),
+            Err(GifError::TooBig {
+                width: 66000,
+                height: 55000
+            })
+        );
+        assert!(matches!(
+            to_gif(&recording, usize::MAX, 10),
+            Err(GifError::TooBig { .. })
+        ));
+    }
+}